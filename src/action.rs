@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+use crate::snake_game::Direction;
+
+pub const ABSOLUTE_ACTIONS: usize = 4;
+pub const RELATIVE_ACTIONS: usize = 3;
+
+/// How the outputs of a network turn into a direction, saved next to it
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ActionSpace {
+    //up, down, left and right, one of them is always turning back on yourself
+    #[default]
+    Absolute,
+    //turn left, straight on and turn right, the same from every heading
+    Relative,
+}
+impl ActionSpace {
+    pub fn num_actions(self) -> usize {
+        match self {
+            ActionSpace::Absolute => ABSOLUTE_ACTIONS,
+            ActionSpace::Relative => RELATIVE_ACTIONS,
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "absolute" => Some(ActionSpace::Absolute),
+            "relative" => Some(ActionSpace::Relative),
+            _ => None,
+        }
+    }
+    /// The direction to move in for the output the network picked, `heading` is where the snake is going now
+    pub fn direction(self, action: usize, heading: Direction) -> Direction {
+        match (self, action) {
+            (ActionSpace::Absolute, 0) => Direction::Up,
+            (ActionSpace::Absolute, 1) => Direction::Down,
+            (ActionSpace::Absolute, 2) => Direction::Left,
+            (ActionSpace::Absolute, 3) => Direction::Right,
+            (ActionSpace::Relative, 0) => heading.turn_left(),
+            (ActionSpace::Relative, 1) => heading,
+            (ActionSpace::Relative, 2) => heading.turn_right(),
+            _ => panic!("Invalid output from network"),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// What a layer does to its outputs, saved with every layer
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Activation {
+    Sigmoid,
+    Tanh,
+    Relu,
+    //slope is what negative values are multiplied by
+    LeakyRelu{slope: f32},
+    Linear,
+    //only makes sense on the output layer, the outputs add up to 1
+    Softmax,
+}
+//files from before activations were saved used a leaky relu on every layer
+impl Default for Activation {
+    fn default() -> Self {
+        Activation::LeakyRelu{slope: 0.01}
+    }
+}
+impl Activation {
+    pub fn apply(self, values: &mut [f32]) {
+        match self {
+            Activation::Sigmoid => values.iter_mut().for_each(|value| *value = 1.0 / (1.0 + (-*value).exp())),
+            Activation::Tanh => values.iter_mut().for_each(|value| *value = value.tanh()),
+            Activation::Relu => values.iter_mut().for_each(|value| *value = value.max(0.0)),
+            Activation::LeakyRelu{slope} => values.iter_mut().for_each(|value| {
+                if *value < 0.0 {
+                    *value *= slope;
+                }
+            }),
+            Activation::Linear => {},
+            Activation::Softmax => {
+                //shifted by the largest value so exp can't overflow
+                let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                values.iter_mut().for_each(|value| *value = (*value - max).exp());
+                let sum: f32 = values.iter().sum();
+                values.iter_mut().for_each(|value| *value /= sum);
+            },
+        }
+    }
+    /// `sigmoid`, `tanh`, `relu`, `leaky`, `leaky:SLOPE`, `linear` or `softmax`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.split_once(':') {
+            None => match name {
+                "sigmoid" => Some(Activation::Sigmoid),
+                "tanh" => Some(Activation::Tanh),
+                "relu" => Some(Activation::Relu),
+                "leaky" => Some(Activation::default()),
+                "linear" => Some(Activation::Linear),
+                "softmax" => Some(Activation::Softmax),
+                _ => None,
+            },
+            Some(("leaky", slope)) => Some(Activation::LeakyRelu{slope: slope.parse().ok()?}),
+            _ => None,
+        }
+    }
+}
+impl Display for Activation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Activation::Sigmoid => write!(f, "sigmoid"),
+            Activation::Tanh => write!(f, "tanh"),
+            Activation::Relu => write!(f, "relu"),
+            Activation::LeakyRelu{slope} => write!(f, "leaky:{}", slope),
+            Activation::Linear => write!(f, "linear"),
+            Activation::Softmax => write!(f, "softmax"),
+        }
+    }
+}
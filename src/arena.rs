@@ -0,0 +1,425 @@
+use std::fmt::Display;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::observation::Planes;
+use crate::snake_game::{
+    Board, CellState, Direction, EndFrameState, FruitKind, GameConfig, GameEvent, GameOverCause, GridPoint, HungerRule, Length, RaySensor,
+    Snake, SnakeSensors
+};
+
+/// Several snakes sharing one board, all moving at the same time and competing for the same fruit
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Arena {
+    board: Board,
+    snakes: Vec<ArenaSnake>,
+    hunger: HungerRule,
+    allow_reversal: bool,
+    //what happened in the last frame
+    #[serde(skip)]
+    events: Vec<ArenaEvent>,
+}
+/// Something that happened in the arena during a frame, `snake` is None for fruit spawning and expiring
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ArenaEvent {
+    pub snake: Option<usize>,
+    pub event: GameEvent,
+}
+impl Display for ArenaEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.snake {
+            Some(snake) => write!(f, "{}: {}", snake_letter(snake), self.event),
+            None => write!(f, "{}", self.event),
+        }
+    }
+}
+fn snake_letter(snake: usize) -> char {
+    (b'A' + (snake % 26) as u8) as char
+}
+#[derive(Clone, Serialize, Deserialize)]
+struct ArenaSnake {
+    snake: Snake,
+    //None while the snake is alive
+    cause: Option<GameOverCause>,
+}
+impl Arena {
+    pub fn new(seed: u64, config: &GameConfig, num_snakes: usize) -> Self {
+        let starts = config.start_points(num_snakes);
+        let mut board = Board::new(ChaCha12Rng::seed_from_u64(seed), config, &starts);
+        let snakes = (0..num_snakes)
+            .map(|i| ArenaSnake {
+                snake: Snake::new(starts.get(i).copied(), &mut board),
+                cause: None,
+            })
+            .collect();
+
+        Self {
+            board,
+            snakes,
+            hunger: config.hunger,
+            allow_reversal: config.allow_reversal,
+            events: Vec::new(),
+        }
+    }
+    pub fn num_snakes(&self) -> usize {
+        self.snakes.len()
+    }
+    //returns false if the input was ignored
+    pub fn accept_input(&mut self, snake: usize, input: Direction) -> bool {
+        let Some(arena_snake) = self.snakes.get_mut(snake) else {return false};
+        if arena_snake.cause.is_some() {
+            return false;
+        }
+        arena_snake.snake.accept_input(input, self.allow_reversal, &self.board)
+    }
+    /// The battle is over once at most one snake is left, or when the only snake dies
+    pub fn is_over(&self) -> bool {
+        let alive = self.snakes.iter().filter(|arena_snake| arena_snake.cause.is_none()).count();
+        alive == 0 || (alive == 1 && self.snakes.len() > 1)
+    }
+    /// `Continue` for snakes that are still alive
+    pub fn results(&self) -> Vec<EndFrameState> {
+        self.snakes
+            .iter()
+            .map(|arena_snake| match arena_snake.cause {
+                Some(cause) => EndFrameState::GameOver{score: arena_snake.snake.length(), cause},
+                None => EndFrameState::Continue,
+            })
+            .collect()
+    }
+    pub fn length(&self, snake: usize) -> Length {
+        self.snakes.get(snake).map(|arena_snake| arena_snake.snake.length()).unwrap_or(0)
+    }
+    pub fn view(&self, snake: usize) -> ArenaSnakeView<'_> {
+        ArenaSnakeView {arena: self, snake}
+    }
+    pub fn events(&self) -> &[ArenaEvent] {
+        &self.events
+    }
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_next_frame(&mut self) -> Vec<EndFrameState> {
+        self.events.clear();
+        if self.is_over() {
+            return self.results();
+        }
+
+        for arena_snake in self.snakes.iter_mut().filter(|arena_snake| arena_snake.cause.is_none()) {
+            arena_snake.snake.kill_tails(&mut self.board);
+        }
+
+        //every snake picks its target before anyone moves
+        let mut targets: Vec<Option<GridPoint>> = vec![None; self.snakes.len()];
+        let mut deaths: Vec<(usize, GameOverCause)> = Vec::new();
+        for i in self.alive() {
+            let snake = &self.snakes[i].snake;
+            let Some(head) = snake.head() else {
+                //there was no room to place the snake
+                deaths.push((i, GameOverCause::HitWall));
+                continue;
+            };
+            match self.board.target(&head, &snake.current_direction()) {
+                Ok(target) => targets[i] = Some(target),
+                Err(GameOverCause::HitSelf) => {
+                    //the board only knows the cell is taken, not by whom
+                    let hit = self.board.neighbor(&head, &snake.current_direction());
+                    let own_body = hit.is_some_and(|hit| snake.cell(&hit).is_some());
+                    deaths.push((i, if own_body {GameOverCause::HitSelf} else {GameOverCause::HitSnake}));
+                },
+                Err(cause) => deaths.push((i, cause)),
+            }
+        }
+        for (i, target) in targets.iter().enumerate() {
+            let Some(target) = target else {continue};
+            let head_on = targets
+                .iter()
+                .enumerate()
+                .any(|(j, other)| j != i && other.as_ref() == Some(target));
+            if head_on {
+                deaths.push((i, GameOverCause::HitSnake));
+            }
+        }
+
+        for (i, target) in targets.into_iter().enumerate() {
+            let Some(target) = target else {continue};
+            if deaths.iter().any(|(dead, _)| *dead == i) {
+                continue;
+            }
+            let snake = &mut self.snakes[i].snake;
+            snake.push_head(target, &mut self.board);
+            let eaten = self.board.take_fruit(&target);
+            if let Some(kind) = eaten {
+                if let Err(cause) = snake.eat(kind, &mut self.board) {
+                    deaths.push((i, cause));
+                }
+            }
+            self.take_board_events(Some(i));
+        }
+        self.remove_snakes(&deaths);
+
+        let heads: Vec<GridPoint> = self.snakes
+            .iter()
+            .filter(|arena_snake| arena_snake.cause.is_none())
+            .filter_map(|arena_snake| arena_snake.snake.head())
+            .collect();
+        let room_left = self.board.refill_fruits(&heads);
+        self.take_board_events(None);
+        if !room_left {
+            let survivors: Vec<(usize, GameOverCause)> = self.alive().map(|i| (i, GameOverCause::Victory)).collect();
+            self.remove_snakes(&survivors);
+            return self.results();
+        }
+
+        let mut starved = Vec::new();
+        for i in self.alive().collect::<Vec<usize>>() {
+            let snake = &mut self.snakes[i].snake;
+            snake.starve();
+            if self.hunger.is_starving(self.board.total_frames(), snake.frames_since_fruit(), snake.length()) {
+                starved.push((i, GameOverCause::Starved));
+            }
+        }
+        self.remove_snakes(&starved);
+
+        if self.board.is_out_of_time() {
+            let timed_out: Vec<(usize, GameOverCause)> = self.alive().map(|i| (i, GameOverCause::TimedOut)).collect();
+            self.remove_snakes(&timed_out);
+        }
+
+        self.results()
+    }
+    fn alive(&self) -> impl Iterator<Item = usize> + '_ {
+        self.snakes
+            .iter()
+            .enumerate()
+            .filter(|(_, arena_snake)| arena_snake.cause.is_none())
+            .map(|(i, _)| i)
+    }
+    //dead snakes leave the board so their cells are free again
+    fn remove_snakes(&mut self, deaths: &[(usize, GameOverCause)]) {
+        for (i, cause) in deaths {
+            let arena_snake = &mut self.snakes[*i];
+            //a snake can only die once, keep the first cause
+            if arena_snake.cause.is_none() {
+                arena_snake.cause = Some(*cause);
+                let event = GameEvent::GameOver{score: arena_snake.snake.length(), cause: *cause};
+                self.events.push(ArenaEvent{snake: Some(*i), event});
+            }
+            arena_snake.snake.clear(&mut self.board);
+        }
+    }
+    fn take_board_events(&mut self, snake: Option<usize>) {
+        let mut events = Vec::new();
+        self.board.drain_events_into(&mut events);
+        self.events.extend(events.into_iter().map(|event| ArenaEvent{snake, event}));
+    }
+    fn owner(&self, point: &GridPoint) -> Option<(usize, CellState)> {
+        if !self.board.is_occupied(point) {
+            return None;
+        }
+        self.snakes
+            .iter()
+            .enumerate()
+            .find_map(|(i, arena_snake)| arena_snake.snake.cell(point).map(|cell| (i, cell)))
+    }
+}
+impl Display for Arena {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        //snakes are drawn as letters, upper case for the head
+        self.board.fmt_cells(f, |point| match self.owner(point) {
+            Some((i, CellState::Head)) => format!("{} ", snake_letter(i)),
+            Some((i, _)) => format!("{} ", snake_letter(i).to_ascii_lowercase()),
+            None => self.board.cell(point).to_string(),
+        })?;
+        for (i, arena_snake) in self.snakes.iter().enumerate() {
+            write!(f, "\n{}: length {}", snake_letter(i), arena_snake.snake.length())?;
+            if let Some(cause) = arena_snake.cause {
+                write!(f, ", {}", cause)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What one snake in the arena senses, other snakes count as obstacles
+pub struct ArenaSnakeView<'a> {
+    arena: &'a Arena,
+    snake: usize,
+}
+impl ArenaSnakeView<'_> {
+    fn snake(&self) -> Option<&Snake> {
+        let arena_snake = self.arena.snakes.get(self.snake)?;
+        if arena_snake.cause.is_some() {
+            return None;
+        }
+        Some(&arena_snake.snake)
+    }
+}
+impl SnakeSensors for ArenaSnakeView<'_> {
+    fn rays(&self) -> [RaySensor; 8] {
+        let Some(head) = self.snake().and_then(|snake| snake.head()) else {return [RaySensor::default(); 8]};
+        self.arena.board.cast_rays(&head)
+    }
+    fn neighboring_cell(&self, direction: Direction) -> CellState {
+        let Some(head) = self.snake().and_then(|snake| snake.head()) else {return CellState::Wall};
+        let Some(neighboring_cell) = self.arena.board.neighbor(&head, &direction) else {
+            return CellState::Wall;
+        };
+
+        match self.arena.owner(&neighboring_cell) {
+            Some((_, cell)) => cell,
+            None => self.arena.board.cell(&neighboring_cell),
+        }
+    }
+    fn egocentric_planes(&self, radius: usize) -> Planes {
+        let Some(own) = self.snake() else {return Planes::new(2 * radius + 1, 2 * radius + 1)};
+        let snakes: Vec<&Snake> = std::iter::once(own)
+            .chain(self.arena.alive().filter(|i| *i != self.snake).map(|i| &self.arena.snakes[i].snake))
+            .collect();
+        self.arena.board.egocentric_planes(&snakes, radius)
+    }
+    fn current_direction(&self) -> Direction {
+        self.snake().map(|snake| snake.current_direction()).unwrap_or(Direction::Right)
+    }
+    fn food_direction(&self) -> Vec<Direction> {
+        let Some(head) = self.snake().and_then(|snake| snake.head()) else {return Vec::new()};
+        self.arena.board.food_direction(&head)
+    }
+    fn nearest_fruit_kind(&self) -> Option<FruitKind> {
+        let head = self.snake().and_then(|snake| snake.head())?;
+        self.arena.board.nearest_fruit_kind(&head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snake_game::FruitSpawn;
+    use crate::snake_game::tests::config;
+    use Direction::{Down, Left, Right, Up};
+
+    //one move per snake every frame
+    fn play(arena: &mut Arena, frames: &[&[Direction]]) -> Vec<EndFrameState> {
+        let mut results = arena.results();
+        for moves in frames {
+            for (snake, direction) in moves.iter().enumerate() {
+                arena.accept_input(snake, *direction);
+            }
+            results = arena.to_next_frame();
+        }
+        results
+    }
+
+    //as drawn, A for the head of the first snake and a for its body
+    fn cell(arena: &Arena, x: usize, y: usize) -> String {
+        let drawn = arena.to_string();
+        let row = drawn.lines().nth(y + 1).expect("the row should be on the board");
+        row.split_whitespace().nth(x).expect("the column should be on the board").to_string()
+    }
+
+    #[test]
+    fn heads_moving_into_the_same_cell_both_die() {
+        //starts at (5, 2) and (5, 4)
+        let config = GameConfig {fruit_spawn: FruitSpawn::Sequence(vec![(0, 0)]), ..GameConfig::new(10, 7)};
+        let mut arena = Arena::new(0, &config, 2);
+        let results = play(&mut arena, &[&[Down, Up]]);
+        let head_on = EndFrameState::GameOver{score: 1, cause: GameOverCause::HitSnake};
+        assert_eq!(results, [head_on, head_on]);
+        assert!(arena.is_over());
+        assert_eq!(cell(&arena, 5, 3), ".");
+    }
+
+    #[test]
+    fn heads_swapping_places_both_die() {
+        let mut arena = Arena::new(0, &config(&[]), 2);
+        play(&mut arena, &[&[Down, Up]]);
+        assert_eq!((cell(&arena, 5, 4), cell(&arena, 5, 5)), ("A".to_string(), "B".to_string()));
+
+        let results = play(&mut arena, &[&[Down, Up]]);
+        let swapped = EndFrameState::GameOver{score: 1, cause: GameOverCause::HitSnake};
+        assert_eq!(results, [swapped, swapped]);
+    }
+
+    #[test]
+    fn a_head_can_move_into_a_tail_that_leaves_this_frame() {
+        let mut arena = Arena::new(0, &config(&[]), 2);
+        play(&mut arena, &[&[Right, Up], &[Right, Up]]);
+        //the last tail of a is about to go
+        assert_eq!(cell(&arena, 5, 3), "a");
+        assert_eq!(cell(&arena, 5, 4), "B");
+
+        let results = play(&mut arena, &[&[Right, Up]]);
+        assert_eq!(results, [EndFrameState::Continue, EndFrameState::Continue]);
+        assert_eq!(cell(&arena, 5, 3), "B");
+        assert_eq!(cell(&arena, 8, 3), "A");
+    }
+
+    #[test]
+    fn running_into_its_own_body_is_hitting_itself() {
+        let mut arena = Arena::new(0, &config(&[(6, 3), (6, 4)]), 2);
+        let results = play(&mut arena, &[&[Right, Right], &[Down, Right], &[Left, Right], &[Up, Right]]);
+        assert_eq!(results, [EndFrameState::GameOver{score: 3, cause: GameOverCause::HitSelf}, EndFrameState::Continue]);
+    }
+
+    #[test]
+    fn running_into_another_body_is_hitting_a_snake() {
+        let mut arena = Arena::new(0, &config(&[(6, 3), (6, 4)]), 2);
+        let results = play(&mut arena, &[&[Right, Right], &[Down, Up], &[Left, Up]]);
+        //(6, 4) is the neck of a
+        assert_eq!(results, [EndFrameState::Continue, EndFrameState::GameOver{score: 1, cause: GameOverCause::HitSnake}]);
+    }
+
+    #[test]
+    fn the_battle_is_over_once_one_snake_is_left() {
+        let mut arena = Arena::new(0, &config(&[]), 2);
+        play(&mut arena, &[&[Up, Right], &[Up, Right], &[Up, Right]]);
+        assert!(!arena.is_over());
+
+        //a runs off the top of the board
+        let results = play(&mut arena, &[&[Up, Right]]);
+        assert_eq!(results, [EndFrameState::GameOver{score: 1, cause: GameOverCause::HitWall}, EndFrameState::Continue]);
+        assert!(arena.is_over());
+
+        //the winner doesn't move any more
+        assert_eq!(play(&mut arena, &[&[Up, Right]]), results);
+        assert_eq!(cell(&arena, 9, 6), "B");
+    }
+
+    #[test]
+    fn every_snake_left_times_out_together() {
+        let mut arena = Arena::new(0, &GameConfig {max_frames: 2, ..config(&[])}, 2);
+        assert_eq!(play(&mut arena, &[&[Right, Right]]), [EndFrameState::Continue, EndFrameState::Continue]);
+
+        let results = play(&mut arena, &[&[Right, Right]]);
+        let timed_out = EndFrameState::GameOver{score: 1, cause: GameOverCause::TimedOut};
+        assert_eq!(results, [timed_out, timed_out]);
+        assert!(arena.is_over());
+    }
+
+    #[test]
+    fn events_say_which_snake_they_belong_to() {
+        let mut arena = Arena::new(0, &config(&[(6, 3)]), 2);
+        play(&mut arena, &[&[Right, Right]]);
+        let event = |snake, event| ArenaEvent{snake, event};
+        assert_eq!(arena.events(), [
+            event(Some(0), GameEvent::Moved{to: (6, 3), direction: Right}),
+            event(Some(0), GameEvent::AteFruit{at: (6, 3), kind: FruitKind::Normal}),
+            event(Some(0), GameEvent::Grew{length: 2}),
+            event(Some(1), GameEvent::Moved{to: (6, 6), direction: Right}),
+            event(None, GameEvent::FruitSpawned{at: (0, 9), kind: FruitKind::Normal}),
+        ]);
+
+        play(&mut arena, &[&[Up, Left], &[Up, Left], &[Up, Left], &[Up, Left]]);
+        assert!(arena.events().contains(&event(Some(0), GameEvent::GameOver{score: 2, cause: GameOverCause::HitWall})));
+    }
+
+    #[test]
+    fn a_single_snake_plays_until_it_dies() {
+        let mut arena = Arena::new(0, &config(&[]), 1);
+        play(&mut arena, &[&[Right], &[Right], &[Right]]);
+        assert!(!arena.is_over());
+
+        let results = play(&mut arena, &[&[Right], &[Right]]);
+        assert_eq!(results, [EndFrameState::GameOver{score: 1, cause: GameOverCause::HitWall}]);
+        assert!(arena.is_over());
+    }
+}
@@ -0,0 +1,45 @@
+/// How a child network is made out of two parents
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Crossover {
+    //every weight and bias comes from either parent
+    Uniform,
+    //every neuron comes whole, weights and bias, from either parent
+    Rows,
+    //every value is the same weighted average of both parents
+    Blend,
+}
+impl Crossover {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "uniform" => Some(Crossover::Uniform),
+            "rows" => Some(Crossover::Rows),
+            "blend" => Some(Crossover::Blend),
+            _ => None,
+        }
+    }
+    /// One neuron of the child, its weights followed by its bias, out of the same neuron of both parents.
+    /// `blend` is the share of the first parent in a blended child, drawn once for the whole network
+    pub fn neuron(self, first: &[f32], second: &[f32], blend: f32, rng: &mut impl rand::Rng) -> Vec<f32> {
+        match self {
+            Crossover::Uniform => first
+                .iter()
+                .zip(second)
+                .map(|(first, second)| if rng.gen() { *first } else { *second })
+                .collect(),
+            Crossover::Rows => if rng.gen() { first.to_vec() } else { second.to_vec() },
+            Crossover::Blend => first
+                .iter()
+                .zip(second)
+                .map(|(first, second)| blend * first + (1.0 - blend) * second)
+                .collect(),
+        }
+    }
+}
+
+//children of two networks with their own step sizes start from the geometric mean of both
+pub fn cross_step_sizes(first: Option<f32>, second: Option<f32>) -> Option<f32> {
+    match (first, second) {
+        (Some(first), Some(second)) => Some((first * second).sqrt()),
+        (first, second) => first.or(second),
+    }
+}
@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+use crate::observation::{Plane, NUM_PLANES};
+use crate::snake_game::{Direction, FruitKind, SnakeSensors};
+
+pub type Observation = Vec<f32>;
+
+pub const SENSOR_INPUTS: usize = 12;
+pub const RELATIVE_INPUTS: usize = 7;
+pub const RAY_INPUTS: usize = 24;
+pub const PLANE_RADIUS: usize = 2;
+pub const PLANE_INPUTS: usize = BASIC_PLANES.len() * (2 * PLANE_RADIUS + 1) * (2 * PLANE_RADIUS + 1);
+//the same with poison, bonus and timed fruit sensed on their own
+pub const FRUIT_SENSOR_INPUTS: usize = SENSOR_INPUTS + 6;
+pub const FRUIT_RELATIVE_INPUTS: usize = RELATIVE_INPUTS + 5;
+pub const FRUIT_RAY_INPUTS: usize = 48;
+pub const FRUIT_PLANE_INPUTS: usize = NUM_PLANES * (2 * PLANE_RADIUS + 1) * (2 * PLANE_RADIUS + 1);
+
+//what the planes encoder saw before fruit kinds had planes of their own, poison was -1 on the fruit plane
+const BASIC_PLANES: [Plane; 4] = [Plane::Head, Plane::Body, Plane::Fruit, Plane::Wall];
+
+/// Turns what a snake senses into network inputs
+pub trait Encoder {
+    fn num_inputs(&self) -> usize;
+    fn encode(&self, game: &dyn SnakeSensors) -> Observation;
+}
+
+/// Which encoder a network was trained with, saved next to it
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EncoderKind {
+    //files from before encoders were saved all used the sensors
+    #[default]
+    Sensors,
+    Relative,
+    Rays,
+    Planes,
+    //the same four, with poison, bonus and timed fruit told apart from normal fruit
+    FruitSensors,
+    FruitRelative,
+    FruitRays,
+    FruitPlanes,
+}
+impl EncoderKind {
+    pub fn encoder(self) -> Box<dyn Encoder + Send + Sync> {
+        match self {
+            EncoderKind::Sensors => Box::new(SensorEncoder{fruit_kinds: false}),
+            EncoderKind::Relative => Box::new(RelativeEncoder{fruit_kinds: false}),
+            EncoderKind::Rays => Box::new(RayEncoder{fruit_kinds: false}),
+            EncoderKind::Planes => Box::new(PlaneEncoder{radius: PLANE_RADIUS, fruit_kinds: false}),
+            EncoderKind::FruitSensors => Box::new(SensorEncoder{fruit_kinds: true}),
+            EncoderKind::FruitRelative => Box::new(RelativeEncoder{fruit_kinds: true}),
+            EncoderKind::FruitRays => Box::new(RayEncoder{fruit_kinds: true}),
+            EncoderKind::FruitPlanes => Box::new(PlaneEncoder{radius: PLANE_RADIUS, fruit_kinds: true}),
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sensors" => Some(EncoderKind::Sensors),
+            "relative" => Some(EncoderKind::Relative),
+            "rays" => Some(EncoderKind::Rays),
+            "planes" => Some(EncoderKind::Planes),
+            "fruit-sensors" => Some(EncoderKind::FruitSensors),
+            "fruit-relative" => Some(EncoderKind::FruitRelative),
+            "fruit-rays" => Some(EncoderKind::FruitRays),
+            "fruit-planes" => Some(EncoderKind::FruitPlanes),
+            _ => None,
+        }
+    }
+}
+
+fn to_inputs(sensors: &[bool]) -> Observation {
+    sensors.iter().map(|sensor| if *sensor { 1.0 } else { 0.0 }).collect()
+}
+
+/// Obstacles next to the head, the current heading and the rough direction of the food. With fruit kinds also
+/// poison next to the head and whether the food is a bonus or timed fruit
+pub struct SensorEncoder {
+    fruit_kinds: bool,
+}
+impl Encoder for SensorEncoder {
+    fn num_inputs(&self) -> usize {
+        if self.fruit_kinds { FRUIT_SENSOR_INPUTS } else { SENSOR_INPUTS }
+    }
+    fn encode(&self, game: &dyn SnakeSensors) -> Observation {
+        let mut inputs = to_inputs(&[
+            game.obstacle_direction_up(),
+            game.obstacle_direction_down(),
+            game.obstacle_direction_left(),
+            game.obstacle_direction_right(),
+            game.current_direction_up(),
+            game.current_direction_down(),
+            game.current_direction_right(),
+            game.current_direction_left(),
+            game.food_direction_up(),
+            game.food_direction_down(),
+            game.food_direction_right(),
+            game.food_direction_left(),
+        ]);
+        if self.fruit_kinds {
+            inputs.extend(to_inputs(&[
+                game.poison_in(Direction::Up),
+                game.poison_in(Direction::Down),
+                game.poison_in(Direction::Left),
+                game.poison_in(Direction::Right),
+                game.food_is(FruitKind::Bonus),
+                game.food_is(FruitKind::Timed),
+            ]));
+        }
+        inputs
+    }
+}
+
+/// The same sensors seen from the snake: obstacles ahead, left and right, and food ahead, left, right and behind.
+/// With fruit kinds also poison ahead, left and right and whether the food is a bonus or timed fruit
+pub struct RelativeEncoder {
+    fruit_kinds: bool,
+}
+impl Encoder for RelativeEncoder {
+    fn num_inputs(&self) -> usize {
+        if self.fruit_kinds { FRUIT_RELATIVE_INPUTS } else { RELATIVE_INPUTS }
+    }
+    fn encode(&self, game: &dyn SnakeSensors) -> Observation {
+        let ahead = game.current_direction();
+        let mut inputs = to_inputs(&[
+            game.obstacle_in(ahead),
+            game.obstacle_in(ahead.turn_left()),
+            game.obstacle_in(ahead.turn_right()),
+            game.food_in(ahead),
+            game.food_in(ahead.turn_left()),
+            game.food_in(ahead.turn_right()),
+            game.food_in(ahead.opposite()),
+        ]);
+        if self.fruit_kinds {
+            inputs.extend(to_inputs(&[
+                game.poison_in(ahead),
+                game.poison_in(ahead.turn_left()),
+                game.poison_in(ahead.turn_right()),
+                game.food_is(FruitKind::Bonus),
+                game.food_is(FruitKind::Timed),
+            ]));
+        }
+        inputs
+    }
+}
+
+/// Wall, body and fruit along 8 rays from the head, with fruit kinds also bonus fruit, timed fruit and poison
+pub struct RayEncoder {
+    fruit_kinds: bool,
+}
+impl Encoder for RayEncoder {
+    fn num_inputs(&self) -> usize {
+        if self.fruit_kinds { FRUIT_RAY_INPUTS } else { RAY_INPUTS }
+    }
+    fn encode(&self, game: &dyn SnakeSensors) -> Observation {
+        game.rays()
+            .iter()
+            .flat_map(|ray| if self.fruit_kinds {
+                vec![ray.wall, ray.body, ray.fruit, ray.bonus, ray.timed, ray.poison]
+            } else {
+                vec![ray.wall, ray.body, ray.fruit]
+            })
+            .collect()
+    }
+}
+
+/// The head, body, fruit and wall planes of the cells around the head, or every plane with fruit kinds
+pub struct PlaneEncoder {
+    radius: usize,
+    fruit_kinds: bool,
+}
+impl Encoder for PlaneEncoder {
+    fn num_inputs(&self) -> usize {
+        let planes = if self.fruit_kinds { NUM_PLANES } else { BASIC_PLANES.len() };
+        planes * (2 * self.radius + 1) * (2 * self.radius + 1)
+    }
+    fn encode(&self, game: &dyn SnakeSensors) -> Observation {
+        let planes = game.egocentric_planes(self.radius);
+        if self.fruit_kinds {
+            return planes.values().to_vec();
+        }
+        BASIC_PLANES
+            .iter()
+            .flat_map(|plane| match plane {
+                Plane::Fruit => planes.plane(Plane::Fruit)
+                    .iter()
+                    .zip(planes.plane(Plane::Poison))
+                    .map(|(fruit, poison)| fruit - poison)
+                    .collect(),
+                _ => planes.plane(*plane).to_vec(),
+            })
+            .collect()
+    }
+}
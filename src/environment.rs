@@ -0,0 +1,101 @@
+use crate::encoder::{Encoder, Observation};
+use crate::replay::Replay;
+use crate::snake_game::{Direction, EndFrameState, GameConfig, GameEvent, GameOverCause, Length, SnakeGame};
+
+/// How much each thing that can happen in one step is worth
+#[derive(Clone, Copy, Debug)]
+pub struct RewardShaping {
+    //per cell the snake grows, shrinking gives the same amount back
+    pub fruit: f32,
+    //added when the game ends any way but victory
+    pub death: f32,
+    //added every step, usually negative to make the snake hurry
+    pub step: f32,
+    //per cell the snake gets closer to the nearest fruit, moving away costs the same
+    pub distance: f32,
+}
+impl Default for RewardShaping {
+    fn default() -> Self {
+        Self {
+            fruit: 1.0,
+            death: -1.0,
+            step: 0.0,
+            distance: 0.0,
+        }
+    }
+}
+
+/// Everything about a step that isn't part of the observation
+#[derive(Clone, Copy, Debug)]
+pub struct StepInfo {
+    pub length: Length,
+    //steps since the last reset
+    pub frames: Length,
+    //false if the action was ignored, such as turning back on yourself
+    pub accepted: bool,
+    //set once the game is over
+    pub cause: Option<GameOverCause>,
+}
+
+/// `SnakeGame` behind a reset / step interface, so learning code doesn't have to drive the game itself
+pub struct SnakeEnv {
+    config: GameConfig,
+    shaping: RewardShaping,
+    encoder: Box<dyn Encoder + Send + Sync>,
+    game: SnakeGame,
+    frames: Length,
+    //every game since the last reset is recorded
+    replay: Replay,
+}
+impl SnakeEnv {
+    pub fn new(config: GameConfig, shaping: RewardShaping, encoder: Box<dyn Encoder + Send + Sync>) -> Self {
+        let game = SnakeGame::new(0, &config);
+        let replay = Replay::new(0, &config);
+        Self {config, shaping, encoder, game, frames: 0, replay}
+    }
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.game = SnakeGame::new(seed, &self.config);
+        self.frames = 0;
+        self.replay = Replay::new(seed, &self.config);
+        self.encoder.encode(&self.game)
+    }
+    /// Returns the observation after the step, the reward, whether the game is over and extra info
+    pub fn step(&mut self, action: Direction) -> (Observation, f32, bool, StepInfo) {
+        let length = self.game.length();
+        let distance = self.game.food_distance();
+
+        let accepted = self.game.accept_input(action);
+        let end = self.game.to_next_frame();
+        self.frames += 1;
+        self.replay.record(action);
+        self.replay.finish(end);
+
+        let mut reward = self.shaping.step;
+        let (new_length, cause) = match end {
+            EndFrameState::Continue => (self.game.length(), None),
+            EndFrameState::GameOver{score, cause} => (score, Some(cause)),
+        };
+        reward += self.shaping.fruit * (new_length as f32 - length as f32);
+        //once fruit was eaten, spawned or expired the nearest fruit may be a different one,
+        //so the change in distance says nothing about this step
+        let fruit_changed = self.game.events().iter().any(|event| matches!(
+            event,
+            GameEvent::AteFruit{..} | GameEvent::FruitSpawned{..} | GameEvent::FruitExpired{..}
+        ));
+        if let (Some(before), Some(after), false) = (distance, self.game.food_distance(), fruit_changed) {
+            reward += self.shaping.distance * (before as f32 - after as f32);
+        }
+        if cause.is_some_and(|cause| !matches!(cause, GameOverCause::Victory | GameOverCause::TimedOut)) {
+            reward += self.shaping.death;
+        }
+
+        let info = StepInfo {length: new_length, frames: self.frames, accepted, cause};
+        (self.encoder.encode(&self.game), reward, end.is_over(), info)
+    }
+    pub fn game(&self) -> &SnakeGame {
+        &self.game
+    }
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+}
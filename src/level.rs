@@ -0,0 +1,54 @@
+use crate::snake_game::GameConfig;
+
+const LEVEL_DIRECTORY: &str = "levels";
+
+/// Loads `levels/<name>.txt`
+pub fn load_level(name: &str) -> Result<GameConfig, String> {
+    let path = format!("{}/{}.txt", LEVEL_DIRECTORY, name);
+    let Ok(string) = std::fs::read_to_string(&path) else {
+        return Err(format!("failed to read level {}", path));
+    };
+    parse_level(&string).map_err(|err| format!("invalid level {}: {}", path, err))
+}
+
+/// Levels are drawn the same way a `Grid` is displayed, one row per line:
+/// `.` empty, `#` wall, `F` fruit and `H` where the snake starts
+pub fn parse_level(string: &str) -> Result<GameConfig, String> {
+    let rows: Vec<Vec<&str>> = string
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .filter(|row| !row.is_empty())
+        .collect();
+
+    let Some(width) = rows.first().map(|row| row.len()) else {
+        return Err("level is empty".to_string());
+    };
+
+    let mut config = GameConfig::new(width as i32, rows.len() as i32);
+
+    for (y, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            return Err(format!("row {} has {} cells, expected {}", y + 1, row.len(), width));
+        }
+        for (x, cell) in row.iter().enumerate() {
+            let point = (x as i32, y as i32);
+            match *cell {
+                "." => {},
+                "#" => config.walls.push(point),
+                "F" => config.fruits.push(point),
+                "H" => {
+                    if config.start.replace(point).is_some() {
+                        return Err("level has more than one H".to_string());
+                    }
+                },
+                _ => return Err(format!("unknown cell {} at ({}, {})", cell, x, y)),
+            }
+        }
+    }
+
+    if config.walls.contains(&config.start_position()) {
+        return Err("the middle of the level is a wall, mark the start with H".to_string());
+    }
+
+    Ok(config)
+}
@@ -1,44 +1,143 @@
-use std::{env, fs::File, io::Write, ops::Div};
-use matrix::ColVector;
-use network::Network;
-use rand::Rng;
+use std::{env, fs::File, io::Write};
+use activation::Activation;
+use crossover::Crossover;
+use selection::{Selection, SelectionStrategy};
+use mutation::Mutation;
+use network::{Brain, DynNetwork, Network, Topology, HIDDEN_SIZE};
 use serde::{Deserialize, Serialize};
-use snake_game::{Direction, EndFrameState, SnakeGame};
+use action::{ActionSpace, ABSOLUTE_ACTIONS, RELATIVE_ACTIONS};
+use arena::Arena;
+use encoder::{
+    Encoder, EncoderKind, Observation, FRUIT_PLANE_INPUTS, FRUIT_RAY_INPUTS, FRUIT_RELATIVE_INPUTS, FRUIT_SENSOR_INPUTS, PLANE_INPUTS,
+    RAY_INPUTS, RELATIVE_INPUTS, SENSOR_INPUTS
+};
+use environment::{RewardShaping, SnakeEnv};
+use replay::Replay;
+use snake_game::{Direction, EndFrameState, FruitSpawn, GameConfig, GameOverCause, HungerRule, Length, PoisonEffect, SnakeGame, SnakeSensors};
 
 mod snake_game;
 mod network;
 mod matrix;
+mod level;
+mod arena;
+mod environment;
+mod replay;
+mod observation;
+mod encoder;
+mod action;
+mod activation;
+mod mutation;
+mod crossover;
+mod selection;
 
-const FILE_NAME: &str = "generation(12,12,4).json";
 const NUM_NETS: usize = 100;
 const NUM_THREADS: u8 = 6;
+const STATE_FILE_NAME: &str = "snake_state.json";
+const REPLAY_FILE_NAME: &str = "replay.json";
+const BEST_REPLAY_FILE_NAME: &str = "best_replay.json";
+const UNDO_LIMIT: usize = 100;
+const VIEW_RADIUS: usize = 2;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let config = match game_config_from_args(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
 
     // 32 megabytes of stack space
     std::thread::Builder::new().stack_size(32 * 1024 * 1024).spawn(move || {
         if let Some(string) = args.get(1){
             match string.as_str() {
-                "train" => {
-                    train_networks(FILE_NAME);
+                "train" | "test" | "battle" => {
+                    let header = match string.as_str() {
+                        //the files say which inputs and actions their networks were trained with
+                        "battle" => header_of_files(&battle_files(&args)),
+                        _ => header_from_args(&args),
+                    };
+                    //one hidden layer of the usual size gets the fixed size network, anything else the slower one sized at runtime
+                    //the number of network outputs is part of the fixed size type, so every action space gets its own copy
+                    match (header, hidden_layers_from_args(&args)) {
+                        (Ok(header), Ok(hidden)) if hidden != [HIDDEN_SIZE] => run_network_command::<DynNetwork>(&args, config, header, &hidden),
+                        (Ok(header), Ok(_)) if header.actions == ActionSpace::Absolute => run_with_encoder::<ABSOLUTE_ACTIONS>(&args, config, header),
+                        (Ok(header), Ok(_)) => run_with_encoder::<RELATIVE_ACTIONS>(&args, config, header),
+                        (Err(err), _) | (_, Err(err)) => println!("{}", err),
+                    }
                 },
-                "test" => {
-                    Generation::load(FILE_NAME).show_best_ever_network();
+                "bench" => {
+                    benchmark(&config);
                 },
                 "play" => {
-                    let mut game = SnakeGame::new(0);
+                    //a game carried on from a saved state can't be replayed from its seed, so it isn't recorded
+                    let (game, mut replay) = match flag_value(&args, "--state").map(SnakeGame::load) {
+                        Some(Ok(game)) => (game, None),
+                        Some(Err(err)) => {
+                            println!("{}, starting a new game", err);
+                            (SnakeGame::new(0, &config), Some(Replay::new(0, &config)))
+                        },
+                        None => (SnakeGame::new(0, &config), Some(Replay::new(0, &config))),
+                    };
+                    let mut game = game.with_history(UNDO_LIMIT);
+                    game.print_frame();
                     loop {
-                        game.accept_input(get_input_from_console());
-                        if matches!(game.to_next_frame(), EndFrameState::GameOver{..}) {
-                            break;
+                        let direction = match get_command_from_console() {
+                            PlayCommand::Move(direction) => direction,
+                            PlayCommand::Save => {
+                                match game.save(STATE_FILE_NAME) {
+                                    Ok(()) => println!("Saved game to {}", STATE_FILE_NAME),
+                                    Err(err) => println!("{}", err),
+                                }
+                                continue;
+                            },
+                            PlayCommand::Undo => {
+                                if game.rewind(1) == 0 {
+                                    println!("Nothing to take back");
+                                    continue;
+                                }
+                                if let Some(replay) = replay.as_mut() {
+                                    replay.undo(1);
+                                }
+                                game.print_frame();
+                                continue;
+                            },
+                            PlayCommand::Observe => {
+                                println!("Board:\n{}", game.planes());
+                                println!("Around the head:\n{}", game.egocentric_planes(VIEW_RADIUS));
+                                continue;
+                            },
+                            PlayCommand::Quit => break,
+                        };
+                        if game.is_over() {
+                            println!("The game is over, u to take back or q to quit");
+                            continue;
+                        }
+                        //an ignored input still takes a frame, the same as it does for networks
+                        let accepted = game.accept_input(direction);
+                        let end = game.to_next_frame();
+                        if let Some(replay) = replay.as_mut() {
+                            replay.record(direction);
+                            replay.finish(end);
                         }
                         game.print_frame();
+                        if !accepted {
+                            println!("Can't turn back on yourself, carried on straight");
+                        }
+                    }
+                    if let Some(replay) = replay {
+                        save_replay(&replay, REPLAY_FILE_NAME);
+                    }
+                },
+                "replay" => {
+                    match args.get(2) {
+                        Some(path) => view_replay(path),
+                        None => println!("replay needs a file"),
                     }
                 },
                 _ => {
                     println!("Invalid argument");
-                    return;
                 }
             }
         }
@@ -46,76 +145,240 @@ fn main() {
     }).unwrap().join().unwrap();
 }
 
+//the same for the number of inputs, every encoder gets its own copy
+fn run_with_encoder<const OUT: usize>(args: &[String], config: GameConfig, header: GenerationHeader) {
+    match header.encoder {
+        EncoderKind::Sensors => run_network_command::<Network<SENSOR_INPUTS, OUT>>(args, config, header, &[HIDDEN_SIZE]),
+        EncoderKind::Relative => run_network_command::<Network<RELATIVE_INPUTS, OUT>>(args, config, header, &[HIDDEN_SIZE]),
+        EncoderKind::Rays => run_network_command::<Network<RAY_INPUTS, OUT>>(args, config, header, &[HIDDEN_SIZE]),
+        EncoderKind::Planes => run_network_command::<Network<PLANE_INPUTS, OUT>>(args, config, header, &[HIDDEN_SIZE]),
+        EncoderKind::FruitSensors => run_network_command::<Network<FRUIT_SENSOR_INPUTS, OUT>>(args, config, header, &[HIDDEN_SIZE]),
+        EncoderKind::FruitRelative => run_network_command::<Network<FRUIT_RELATIVE_INPUTS, OUT>>(args, config, header, &[HIDDEN_SIZE]),
+        EncoderKind::FruitRays => run_network_command::<Network<FRUIT_RAY_INPUTS, OUT>>(args, config, header, &[HIDDEN_SIZE]),
+        EncoderKind::FruitPlanes => run_network_command::<Network<FRUIT_PLANE_INPUTS, OUT>>(args, config, header, &[HIDDEN_SIZE]),
+    }
+}
+
+fn run_network_command<B: Brain>(args: &[String], config: GameConfig, header: GenerationHeader, hidden: &[usize]) {
+    let topology = match activations_from_args(args, hidden.len() + 1) {
+        Ok(activations) => Topology{layer_sizes: header.layer_sizes(hidden), activations},
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let file_name = generation_file_name(header.encoder, &topology.layer_sizes);
+    match args[1].as_str() {
+        "train" => {
+            match training_from_args(args, config, header) {
+                Ok(training) => train_networks::<B>(&file_name, &training, &topology),
+                Err(err) => println!("{}", err),
+            }
+        },
+        "test" => {
+            let generation = reward_shaping_from_args(args)
+                .and_then(|shaping| Ok((Generation::<B>::load(&file_name, header, &topology)?, shaping)));
+            match generation {
+                Ok((generation, shaping)) => generation.show_best_ever_network(&config, shaping.unwrap_or_default()),
+                Err(err) => println!("{}", err),
+            }
+        },
+        "battle" => {
+            battle::<B>(&battle_files(args), &config, header, &topology);
+        },
+        _ => println!("Invalid argument"),
+    }
+}
+
+//named after the layer sizes, the encoders that tell fruit kinds apart can have the same sizes as the others
+//so they get files of their own
+fn generation_file_name(encoder: EncoderKind, layer_sizes: &[usize]) -> String {
+    let sizes: Vec<String> = layer_sizes.iter().map(|size| size.to_string()).collect();
+    match encoder {
+        EncoderKind::Sensors | EncoderKind::Relative | EncoderKind::Rays | EncoderKind::Planes =>
+            format!("generation({}).json", sizes.join(",")),
+        _ => format!("fruit_generation({}).json", sizes.join(",")),
+    }
+}
+
 type Score = f32;
 
+/// How networks are scored and bred during training
+#[derive(Clone)]
+struct Training{
+    config: GameConfig,
+    encoder: EncoderKind,
+    actions: ActionSpace,
+    mutation: Mutation,
+    //children are copies of one parent without it
+    crossover: Option<Crossover>,
+    //whatever isn't set is carried on from the generation being trained
+    strategy: Option<SelectionStrategy>,
+    elites: Option<usize>,
+    //score in an arena against this many copies of the best ever network
+    num_opponents: usize,
+    //score by the total reward instead of the final length
+    reward: Option<RewardShaping>,
+}
+
+impl Training{
+    fn header(&self) -> GenerationHeader{
+        GenerationHeader{encoder: self.encoder, actions: self.actions}
+    }
+    fn selection(&self, recorded: Selection) -> Selection{
+        Selection{
+            strategy: self.strategy.unwrap_or(recorded.strategy),
+            elites: self.elites.unwrap_or(recorded.elites),
+        }
+    }
+}
+
+/// How many of the games played while scoring a generation ended each way
+#[derive(Clone, Copy, Default)]
+struct DeathCauses{
+    hit_wall: u32,
+    hit_self: u32,
+    hit_snake: u32,
+    starved: u32,
+    poisoned: u32,
+    victory: u32,
+    //arena games where every opponent died first
+    outlived: u32,
+    timed_out: u32,
+}
+impl DeathCauses{
+    fn add(&mut self, cause: GameOverCause){
+        match cause {
+            GameOverCause::HitWall => self.hit_wall += 1,
+            GameOverCause::HitSelf => self.hit_self += 1,
+            GameOverCause::HitSnake => self.hit_snake += 1,
+            GameOverCause::Starved => self.starved += 1,
+            GameOverCause::Poisoned => self.poisoned += 1,
+            GameOverCause::Victory => self.victory += 1,
+            GameOverCause::TimedOut => self.timed_out += 1,
+        }
+    }
+    fn merge(&mut self, other: &DeathCauses){
+        self.hit_wall += other.hit_wall;
+        self.hit_self += other.hit_self;
+        self.hit_snake += other.hit_snake;
+        self.starved += other.starved;
+        self.poisoned += other.poisoned;
+        self.victory += other.victory;
+        self.outlived += other.outlived;
+        self.timed_out += other.timed_out;
+    }
+}
+impl std::fmt::Display for DeathCauses{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f, "hit wall: {}, hit self: {}, starved: {}, poisoned: {}, victory: {}",
+            self.hit_wall, self.hit_self, self.starved, self.poisoned, self.victory
+        )?;
+        //only arena training can have these
+        if self.hit_snake > 0 || self.outlived > 0 {
+            write!(f, ", hit snake: {}, outlived: {}", self.hit_snake, self.outlived)?;
+        }
+        if self.timed_out > 0 {
+            write!(f, ", timed out: {}", self.timed_out)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
-struct Generation{
-    best_ever_network: (Network, Score),
-    networks: Vec<(Network, Score)>,
+struct Generation<B>{
+    best_ever_network: (B, Score),
+    networks: Vec<(B, Score)>,
     generation_counter: u64,
+    //the networks only make sense with the inputs and actions they were trained on
+    #[serde(default)]
+    encoder: EncoderKind,
+    #[serde(default)]
+    actions: ActionSpace,
+    //how it was picked out of the last generation, training carries on the same way unless told otherwise
+    #[serde(default)]
+    selection: Selection,
+    #[serde(skip)]
+    death_causes: DeathCauses,
+}
+/// Just the part of a generation file that says how to read the rest
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+struct GenerationHeader{
+    #[serde(default)]
+    encoder: EncoderKind,
+    #[serde(default)]
+    actions: ActionSpace,
+}
+impl GenerationHeader{
+    //inputs first and outputs last
+    fn layer_sizes(&self, hidden: &[usize]) -> Vec<usize>{
+        std::iter::once(self.encoder.encoder().num_inputs())
+            .chain(hidden.iter().copied())
+            .chain(std::iter::once(self.actions.num_actions()))
+            .collect()
+    }
 }
-impl Generation{
-    fn new(num_networks: usize)->Self{
+impl std::fmt::Display for GenerationHeader{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} inputs and {:?} actions", self.encoder, self.actions)
+    }
+}
+impl<B: Brain> Generation<B>{
+    fn new(num_networks: usize, header: GenerationHeader, topology: &Topology)->Self{
         Self{
-            best_ever_network: (Network::new(&mut rand::thread_rng()), 0.0),
-            networks: (0..num_networks).map(|_| (Network::new(&mut rand::thread_rng()), 0.0)).collect(),
+            best_ever_network: (B::new_random(topology, &mut rand::thread_rng()), 0.0),
+            networks: (0..num_networks).map(|_| (B::new_random(topology, &mut rand::thread_rng()), 0.0)).collect(),
             generation_counter: 0,
+            encoder: header.encoder,
+            actions: header.actions,
+            selection: Selection::default(),
+            death_causes: DeathCauses::default(),
         }
     }
-    fn new_from_generation(parents: &Generation)->Self{
+    fn new_from_generation(parents: &Generation<B>, training: &Training)->Self{
         let rand = &mut rand::thread_rng();
+        let selection = training.selection(parents.selection);
 
-        let (_, max) = parents.score_range(1.0f32);
-
-        let num_parents = parents.networks.len();
-        let mut new_networks: Vec<(Network, Score)> = parents.networks
+        //the networks are sorted best first after scoring, the best ever one only breeds if it's still among them
+        let scores: Vec<Score> = parents.networks.iter().map(|(_, score)| *score).collect();
+        let mut new_networks: Vec<(B, Score)> = parents.networks
             .iter()
-            .filter_map(|(network, score)|{
-
-                let percent_score = score.div(max);
-
-                if rand.gen::<f32>() % 1.0 < percent_score * percent_score {
-                    Some((network.clone(), *score))
-                }else{
-                    None
-                }
-
-            })
+            .take(selection.elites.min(NUM_NETS))
+            .cloned()
             .collect();
 
-        new_networks.push(parents.best_ever_network.clone());
-
-        let num_culled_networks = new_networks.len();
-
-        let mut i = 0;
-        while new_networks.len() < num_parents {
-
-            if let Some(parent) = new_networks.get(i % num_culled_networks) {
-
-                let mut new_network = parent.0.clone();
-                new_network.randomly_edit(rand);
-                new_networks.push((new_network, 0.0));
-                
-            }else{
-                i = 0;
-            }
-
-            i += 1;
+        while new_networks.len() < NUM_NETS {
+            let parent = &parents.networks[selection.strategy.pick(&scores, rand)].0;
+            let mut new_network = match training.crossover {
+                Some(crossover) => {
+                    let other = &parents.networks[selection.strategy.pick(&scores, rand)].0;
+                    parent.crossover(other, crossover, rand)
+                },
+                None => parent.clone(),
+            };
+            new_network.mutate(&training.mutation, rand);
+            new_networks.push((new_network, 0.0));
         }
 
-        
         let mut out = Self{
             best_ever_network: parents.best_ever_network.clone(),
             networks: new_networks,
             generation_counter: parents.generation_counter.saturating_add(1),
+            encoder: parents.encoder,
+            actions: parents.actions,
+            selection,
+            death_causes: DeathCauses::default(),
         };
         
-        out.train_scores();
+        out.train_scores(training);
 
         out
     }
-    fn train_scores(&mut self){
-        self.networks = train_scores_on_multiple_threads(self.networks.drain(..).collect(), NUM_THREADS);
+    //with opponents every network is scored in an arena against copies of the best ever network
+    fn train_scores(&mut self, training: &Training){
+        let opponents = vec![self.best_ever_network.0.clone(); training.num_opponents];
+        (self.networks, self.death_causes) = train_scores_on_multiple_threads(self.networks.drain(..).collect(), NUM_THREADS, training, &opponents);
         
         self.networks.sort_by(|a, b|
             b.1.partial_cmp(&a.1).unwrap()
@@ -127,44 +390,60 @@ impl Generation{
             }
         }
     }
-    fn show_best_ever_network(&self){
-        let mut game = SnakeGame::new(self.generation_counter);
+    fn show_best_ever_network(&self, config: &GameConfig, shaping: RewardShaping){
+        let mut env = SnakeEnv::new(config.clone(), shaping, self.encoder.encoder());
+        let mut observation = env.reset(self.generation_counter);
+        let mut total_reward = 0.0;
 
         loop {
-            let direction = get_input_from_network(&game, &self.best_ever_network.0);
-            game.accept_input(direction);
+            let direction = get_input_from_observation(&observation, &self.best_ever_network.0, self.actions, env.game().current_direction());
+            let (next_observation, reward, done, info) = env.step(direction);
+            observation = next_observation;
+            total_reward += reward;
             
-            
-            if matches!(game.to_next_frame(), EndFrameState::GameOver{..}) {
+            if done {
                 break;
             }
 
-            game.print_frame();
-            println!("input: {:?}", direction);
+            env.game().print_frame();
+            println!(
+                "frame: {}, input: {:?}{}, length: {}, reward: {}",
+                info.frames, direction, if info.accepted { "" } else { " (ignored)" }, info.length, total_reward
+            );
+            for event in env.game().events() {
+                println!("{}", event);
+            }
     
             std::thread::sleep(std::time::Duration::from_millis(100));
         }
+
+        save_replay(env.replay(), REPLAY_FILE_NAME);
+    }
+    //the same game the best ever network gets scored on first. Replays only hold one snake,
+    //so with opponents it's recorded playing the same seed on its own
+    fn record_best_ever_network(&self, training: &Training) -> Replay {
+        let mut env = SnakeEnv::new(training.config.clone(), RewardShaping::default(), self.encoder.encoder());
+        let mut observation = env.reset(0);
+
+        loop {
+            let direction = get_input_from_observation(&observation, &self.best_ever_network.0, self.actions, env.game().current_direction());
+            let (next_observation, _, done, _) = env.step(direction);
+            observation = next_observation;
+            if done {
+                break;
+            }
+        }
+
+        env.replay().clone()
     }
     fn mean_score(&self, top_percent: f32)->f32{
         let num = (self.networks.len() as f32 * top_percent) as usize;
         self.networks
             .iter()
             .take(num)
-            .map(|(_, score)| *score as f32)
+            .map(|(_, score)| *score)
             .sum::<f32>() / num as f32
     }
-    
-    /// (Min, Max)
-    fn score_range(&self, top_percent: f32)->(Score, Score){
-        let num = (self.networks.len() as f32 * top_percent) as usize;
-        self.networks
-            .iter()
-            .take(num)
-            .map(|(_, score)| *score as f32)
-            .fold((std::f32::MAX, std::f32::MIN), |(min, max), score| {
-                (min.min(score), max.max(score))
-            })
-    }
 
     fn save(&self, path: &str){
         let Ok(string) = serde_json::to_string(self) else {
@@ -184,146 +463,566 @@ impl Generation{
         
         println!("Saved generation");
     }
-    fn load(path: &str)->Self{
+    //refuses files trained with different inputs, actions, layers or activations instead of replacing them with random networks
+    fn load(path: &str, header: GenerationHeader, topology: &Topology)->Result<Self, String>{
         let Ok(string) = std::fs::read_to_string(path) else {
             println!("failed to read file, generating random");
-            return Self::new(NUM_NETS);
+            return Ok(Self::new(NUM_NETS, header, topology));
         };
 
-        let Ok(generation) = serde_json::from_str::<Generation>(&string) else {
-            println!("failed to deserialize, generating random");
-            return Self::new(NUM_NETS);
-        };
+        //checked first, the networks won't fit if the encoder or actions are different
+        if let Ok(saved) = serde_json::from_str::<GenerationHeader>(&string) {
+            if saved != header {
+                return Err(format!("{} was trained with {}, not {}", path, saved, header));
+            }
+        }
+
+        let generation = serde_json::from_str::<Generation<B>>(&string)
+            .map_err(|err| format!("invalid generation {}: {}", path, err))?;
+
+        let mut networks = generation.networks.iter().chain(std::iter::once(&generation.best_ever_network));
+        if let Some((network, _)) = networks.find(|(network, _)| network.topology() != *topology) {
+            return Err(format!("{} has {}, not {}", path, network.topology(), topology));
+        }
 
         println!("loaded generation {}", path);
 
-        generation
+        Ok(generation)
     }
 }
 
 
-fn train_scores_on_multiple_threads(mut networks: Vec<(Network, Score)>, num_threads: u8)->Vec<(Network, Score)>{
+fn train_scores_on_multiple_threads<B: Brain>(mut networks: Vec<(B, Score)>, num_threads: u8, training: &Training, opponents: &[B])->(Vec<(B, Score)>, DeathCauses){
 
-    let chunk_size = (networks.len() + num_threads as usize - 1) / num_threads as usize; // Round up
+    let chunk_size = networks.len().div_ceil(num_threads as usize);
     let mut handles = Vec::new();
 
-    let chunks: Vec<Vec<(Network, Score)>> = networks
+    let chunks: Vec<Vec<(B, Score)>> = networks
         .chunks_mut(chunk_size)
         .map(|chunk| chunk.to_vec()) // Avoids split_off overhead
         .collect();
 
     for chunk in chunks {
-        handles.push(std::thread::spawn(move || train_scores_single_thread(chunk)));
+        let training = training.clone();
+        let opponents = opponents.to_vec();
+        handles.push(std::thread::spawn(move || train_scores_single_thread(chunk, &training, &opponents)));
     }
 
     let mut results = Vec::new();
+    let mut death_causes = DeathCauses::default();
     for handle in handles {
-        results.append(&mut handle.join().unwrap()); // Collect results
+        let (mut chunk, chunk_death_causes) = handle.join().unwrap();
+        results.append(&mut chunk); // Collect results
+        death_causes.merge(&chunk_death_causes);
     }
 
-    results
+    (results, death_causes)
 }
-fn train_scores_single_thread(mut networks: Vec<(Network, Score)>)->Vec<(Network, Score)>{
+fn train_scores_single_thread<B: Brain>(mut networks: Vec<(B, Score)>, training: &Training, opponents: &[B])->(Vec<(B, Score)>, DeathCauses){
+    let mut death_causes = DeathCauses::default();
+    let mut env = SnakeEnv::new(training.config.clone(), training.reward.unwrap_or_default(), training.encoder.encoder());
     for (network, score) in networks.iter_mut(){
-        *score = get_score(network, 0);
+        *score = if opponents.is_empty() {
+            get_score(network, 0, &mut env, training.actions, training.reward.is_some(), &mut death_causes)
+        } else {
+            get_arena_score(network, opponents, 0, training, &mut death_causes)
+        };
     }
-    networks
+    (networks, death_causes)
 }
 
 
 
 
-fn train_networks(file_name: &str) {
+fn train_networks<B: Brain>(file_name: &str, training: &Training, topology: &Topology) {
 
-    let mut generation = Generation::load(file_name);
+    let mut generation = match Generation::<B>::load(file_name, training.header(), topology) {
+        Ok(generation) => generation,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    println!("Selecting by {}", training.selection(generation.selection));
 
     loop {
-        generation = Generation::new_from_generation(&generation);
-        if generation.generation_counter % 10 == 0 {
+        generation = Generation::new_from_generation(&generation, training);
+        if generation.generation_counter.is_multiple_of(10) {
             println!(
-                "Generation {}: Best Ever: {}, Avg score: {}",
+                "Generation {}: Best Ever: {}, Avg score: {}, Deaths: {}",
                 generation.generation_counter,
                 generation.best_ever_network.1,
                 generation.mean_score(0.3f32),
+                generation.death_causes,
             );
             generation.save(file_name);
+            save_replay(&generation.record_best_ever_network(training), BEST_REPLAY_FILE_NAME);
         }
     }
     
 }
 
-fn get_score(net: &Network, seed: u64) -> f32 {
+fn save_replay(replay: &Replay, path: &str) {
+    match replay.save(path) {
+        Ok(()) => println!("Saved replay {}", path),
+        Err(err) => println!("{}", err),
+    }
+}
+
+/// Plays a replay again one frame at a time, and checks it still ends the way it did when it was recorded
+fn view_replay(path: &str) {
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let (frames, end) = replay.simulate();
+    let verdict = if end == replay.end() {
+        "matches the recording".to_string()
+    } else {
+        format!("ended with {:?}, but was recorded as {:?}", end, replay.end())
+    };
+
+    let mut i = 0;
+    loop {
+        frames[i].print_frame();
+        println!("frame {}/{}, replay {}", i, frames.len() - 1, verdict);
+        println!("enter: next frame, b: back, q: quit");
+
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+            break;
+        }
+        match input.trim() {
+            "" | "n" => i = (i + 1).min(frames.len() - 1),
+            "b" => i = i.saturating_sub(1),
+            "q" => break,
+            _ => println!("Invalid input"),
+        }
+    }
+}
+
+//the score is the final length, or the total reward when use_reward is set
+fn get_score<B: Brain>(net: &B, seed: u64, env: &mut SnakeEnv, actions: ActionSpace, use_reward: bool, death_causes: &mut DeathCauses) -> f32 {
 
     const NUM_SIMULATIONS: i32 = 3;
 
-    let mut avg_score = 0;
+    let mut avg_score: f32 = 0.0;
 
     for i in 0..NUM_SIMULATIONS {
         
-        let mut game = SnakeGame::new(seed+(i as u64));
-        let mut num_frames: i32 = 0;
+        let mut observation = env.reset(seed+(i as u64));
+        let mut total_reward = 0.0;
         
         //run game
         let score = loop {
-            game.accept_input(get_input_from_network(&game, &net));
+            let direction = get_input_from_observation(&observation, net, actions, env.game().current_direction());
+            let (next_observation, reward, done, info) = env.step(direction);
+            observation = next_observation;
+            total_reward += reward;
 
-            num_frames += 1;
-            
-            if let EndFrameState::GameOver{score} = game.to_next_frame() {
-                break score as i32;
+            if let (true, Some(cause)) = (done, info.cause) {
+                death_causes.add(cause);
+                break if use_reward { total_reward } else { info.length as f32 };
             }
+        };
+
+        avg_score += score;
+    }
+
+    avg_score / NUM_SIMULATIONS as f32
+}
+
+//the network plays snake 0, the score is its length when it dies or the battle ends
+fn get_arena_score<B: Brain>(net: &B, opponents: &[B], seed: u64, training: &Training, death_causes: &mut DeathCauses) -> f32 {
+
+    const NUM_SIMULATIONS: i32 = 3;
+
+    let mut avg_score: u64 = 0;
+    let encoder = training.encoder.encoder();
+
+    for i in 0..NUM_SIMULATIONS {
 
-            let snake_length = game.length() as i32;
-            if num_frames > (200 + (snake_length * 50)) {
-                break snake_length;
+        let mut arena = Arena::new(seed+(i as u64), &training.config, opponents.len() + 1);
+
+        let score = loop {
+            for (snake, network) in std::iter::once(net).chain(opponents).enumerate() {
+                let direction = get_input_from_network(&arena.view(snake), network, encoder.as_ref(), training.actions);
+                arena.accept_input(snake, direction);
             }
 
+            let results = arena.to_next_frame();
+            if let EndFrameState::GameOver{score, cause} = results[0] {
+                death_causes.add(cause);
+                break score;
+            }
+            if arena.is_over() {
+                death_causes.outlived += 1;
+                break arena.length(0);
+            }
         };
 
-        avg_score += score;
+        avg_score += score as u64;
     }
 
     avg_score as f32 / NUM_SIMULATIONS as f32
 }
 
+/// Loads the best ever network of every file and lets them fight on one board
+fn battle<B: Brain>(files: &[&str], config: &GameConfig, header: GenerationHeader, topology: &Topology) {
+    let networks: Result<Vec<B>, String> = files
+        .iter()
+        .map(|file_name| Ok(Generation::<B>::load(file_name, header, topology)?.best_ever_network.0))
+        .collect();
+    let networks = match networks {
+        Ok(networks) => networks,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let encoder = header.encoder.encoder();
+    let mut arena = Arena::new(0, config, networks.len());
+
+    while !arena.is_over() {
+        for (snake, network) in networks.iter().enumerate() {
+            let direction = get_input_from_network(&arena.view(snake), network, encoder.as_ref(), header.actions);
+            arena.accept_input(snake, direction);
+        }
+        arena.to_next_frame();
+
+        println!("{}\n", arena);
+        for event in arena.events() {
+            println!("{}", event);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    let results = arena.results();
+    for snake in 0..arena.num_snakes() {
+        match results[snake] {
+            EndFrameState::GameOver{score, cause} => println!("{}: {}, Score: {}", files[snake], cause, score),
+            EndFrameState::Continue => println!("{}: Winner, Score: {}", files[snake], arena.length(snake)),
+        }
+    }
+}
+
+
+/// Plays a fixed set of games with a simple greedy policy and reports the simulation speed,
+/// the total length is printed so runs of different engine versions can be compared
+fn benchmark(config: &GameConfig) {
+    const NUM_GAMES: u64 = 2000;
+
+    let start = std::time::Instant::now();
+    let mut num_frames: u64 = 0;
+    let mut total_length: u64 = 0;
+
+    for seed in 0..NUM_GAMES {
+        let mut game = SnakeGame::new(seed, config);
+        loop {
+            game.accept_input(get_greedy_input(&game));
+            num_frames += 1;
+            if let Some(score) = game.to_next_frame().final_score() {
+                total_length += score as u64;
+                break;
+            }
+        }
+    }
+
+    let elapsed = start.elapsed();
+    println!(
+        "{} games, {} frames in {:.2?}: {:.0} frames/sec, total length {}",
+        NUM_GAMES,
+        num_frames,
+        elapsed,
+        num_frames as f64 / elapsed.as_secs_f64(),
+        total_length,
+    );
+}
+
+/// Reads `--level NAME` or `--size WIDTHxHEIGHT` (defaulting to a 10x10 board), `--wrap`
+/// `--hunger none|total|fruit[:BASE:PER_LENGTH]`, `--allow-reversal`, `--fruits N`
+/// `--fruit-spawn uniform|far|sequence:X,Y/X,Y/...`, `--bonus-fruit CHANCE`, `--poison-fruit CHANCE`,
+/// `--timed-fruit CHANCE`, `--poison-kills` and `--max-frames N`
+fn game_config_from_args(args: &[String]) -> Result<GameConfig, String> {
+    let mut config = GameConfig::default();
 
-fn get_input_from_console() -> Direction {
+    match (flag_value(args, "--level"), flag_value(args, "--size")) {
+        //a level has its own size
+        (Some(_), Some(_)) => return Err("--size can't be used with --level".to_string()),
+        (Some(name), None) => config = level::load_level(name)?,
+        (None, Some(size)) => match size.split_once('x').map(|(w, h)| (w.parse::<i32>(), h.parse::<i32>())) {
+            Some((Ok(width), Ok(height))) if width > 0 && height > 0 => {
+                config = GameConfig::new(width, height);
+            },
+            _ => return Err(format!("Invalid size {}", size)),
+        },
+        (None, None) => {},
+    }
+    config.wrap = has_flag(args, "--wrap");
+    config.allow_reversal = has_flag(args, "--allow-reversal");
+    if let Some(fruits) = flag_value(args, "--fruits") {
+        config.fruit_count = match fruits.parse::<usize>() {
+            Ok(fruit_count) if fruit_count > 0 => fruit_count,
+            _ => return Err(format!("Invalid number of fruits {}", fruits)),
+        };
+    }
+    if let Some(fruit_spawn) = flag_value(args, "--fruit-spawn") {
+        config.fruit_spawn = parse_fruit_spawn(fruit_spawn).ok_or(format!("Invalid fruit spawn {}", fruit_spawn))?;
+    }
+    for (flag, chance) in [
+        ("--bonus-fruit", &mut config.special_fruits.bonus_chance),
+        ("--poison-fruit", &mut config.special_fruits.poison_chance),
+        ("--timed-fruit", &mut config.special_fruits.timed_chance),
+    ] {
+        if let Some(value) = flag_value(args, flag) {
+            *chance = match value.parse::<f32>() {
+                Ok(value) if (0.0..=1.0).contains(&value) => value,
+                _ => return Err(format!("Invalid chance {} for {}", value, flag)),
+            };
+        }
+    }
+    if has_flag(args, "--poison-kills") {
+        config.special_fruits.poison = PoisonEffect::Kill;
+    }
+    if let Some(hunger) = flag_value(args, "--hunger") {
+        config.hunger = parse_hunger_rule(hunger).ok_or(format!("Invalid hunger rule {}", hunger))?;
+    }
+    if let Some(max_frames) = flag_value(args, "--max-frames") {
+        config.max_frames = match max_frames.parse::<Length>() {
+            Ok(max_frames) if max_frames > 0 => max_frames,
+            _ => return Err(format!("Invalid max frames {}", max_frames)),
+        };
+    }
+
+    Ok(config)
+}
+fn parse_hunger_rule(string: &str) -> Option<HungerRule> {
+    let mut parts = string.split(':');
+    let mode = parts.next()?;
+    let (base, per_length) = match (parts.next(), parts.next()) {
+        (Some(base), Some(per_length)) => (base.parse().ok()?, per_length.parse().ok()?),
+        (None, None) => (200, 50),
+        _ => return None,
+    };
+
+    match mode {
+        "none" => Some(HungerRule::Unlimited),
+        "total" => Some(HungerRule::TotalFrames{base, per_length}),
+        "fruit" => Some(HungerRule::FramesSinceFruit{base, per_length}),
+        _ => None,
+    }
+}
+fn parse_fruit_spawn(string: &str) -> Option<FruitSpawn> {
+    match string.split_once(':') {
+        None if string == "uniform" => Some(FruitSpawn::Uniform),
+        None if string == "far" => Some(FruitSpawn::FarFromHead),
+        Some(("sequence", points)) => points
+            .split('/')
+            .map(|point| {
+                let (x, y) = point.split_once(',')?;
+                Some((x.parse().ok()?, y.parse().ok()?))
+            })
+            .collect::<Option<Vec<(i32, i32)>>>()
+            .map(FruitSpawn::Sequence),
+        _ => None,
+    }
+}
+/// Reads `--opponents N`, `--reward`, `--mutation`, `--adaptive`, `--crossover`, `--selection` and `--elites N`
+fn training_from_args(args: &[String], config: GameConfig, header: GenerationHeader) -> Result<Training, String> {
+    let num_opponents = match flag_value(args, "--opponents") {
+        Some(value) => value.parse::<usize>().map_err(|_| format!("Invalid number of opponents {}", value))?,
+        None => 0,
+    };
+    Ok(Training{
+        config,
+        encoder: header.encoder,
+        actions: header.actions,
+        mutation: mutation_from_args(args)?,
+        crossover: crossover_from_args(args)?,
+        strategy: flag_value(args, "--selection")
+            .map(|name| SelectionStrategy::from_name(name).ok_or(format!("Invalid selection {}", name)))
+            .transpose()?,
+        elites: flag_value(args, "--elites")
+            .map(|value| value.parse::<usize>().map_err(|_| format!("Invalid number of elites {}", value)))
+            .transpose()?,
+        num_opponents,
+        reward: reward_shaping_from_args(args)?,
+    })
+}
+/// Reads `--mutation RATE:SIGMA:RESET` and `--adaptive`
+fn mutation_from_args(args: &[String]) -> Result<Mutation, String> {
+    let adaptive = has_flag(args, "--adaptive");
+    let Some(value) = flag_value(args, "--mutation") else {
+        return Ok(Mutation{adaptive, ..Mutation::default()});
+    };
+    let values = value
+        .split(':')
+        .map(|value| value.parse::<f32>().ok())
+        .collect::<Option<Vec<f32>>>();
+    match values.as_deref() {
+        //rate and reset rate are chances, NaN is outside the range too
+        Some(&[rate, sigma, reset_rate])
+            if (0.0..=1.0).contains(&rate) && sigma >= 0.0 && (0.0..=1.0).contains(&reset_rate) =>
+            Ok(Mutation{rate, sigma, reset_rate, adaptive}),
+        _ => Err(format!("Invalid mutation {}", value)),
+    }
+}
+/// Reads `--crossover uniform|rows|blend|none`
+fn crossover_from_args(args: &[String]) -> Result<Option<Crossover>, String> {
+    match flag_value(args, "--crossover") {
+        None => Ok(Some(Crossover::Uniform)),
+        Some("none") => Ok(None),
+        Some(name) => Crossover::from_name(name).map(Some).ok_or(format!("Invalid crossover {}", name)),
+    }
+}
+/// Reads `--hidden WIDTH,WIDTH,...`, the size of every hidden layer, or `--hidden none`
+fn hidden_layers_from_args(args: &[String]) -> Result<Vec<usize>, String> {
+    match flag_value(args, "--hidden") {
+        None => Ok(vec![HIDDEN_SIZE]),
+        Some("none") => Ok(Vec::new()),
+        Some(value) => value
+            .split(',')
+            .map(|width| width.parse::<usize>().ok().filter(|width| *width > 0))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or(format!("Invalid hidden layers {}", value)),
+    }
+}
+/// Reads `--activations NAME,NAME,...`, one for every layer after the inputs or one for all of them
+fn activations_from_args(args: &[String], num_layers: usize) -> Result<Vec<Activation>, String> {
+    let Some(value) = flag_value(args, "--activations") else {
+        return Ok(vec![Activation::default(); num_layers]);
+    };
+    let activations = value
+        .split(',')
+        .map(Activation::from_name)
+        .collect::<Option<Vec<Activation>>>()
+        .ok_or(format!("Invalid activations {}", value))?;
+    let activations = match activations.as_slice() {
+        &[activation] => vec![activation; num_layers],
+        _ if activations.len() == num_layers => activations,
+        _ => return Err(format!("{} activations given for {} layers", activations.len(), num_layers)),
+    };
+    if activations[..num_layers - 1].contains(&Activation::Softmax) {
+        return Err("softmax only works on the output layer".to_string());
+    }
+    Ok(activations)
+}
+/// Reads `--inputs sensors|relative|rays|planes` and `--actions absolute|relative`
+fn header_from_args(args: &[String]) -> Result<GenerationHeader, String> {
+    let actions = match flag_value(args, "--actions") {
+        None => ActionSpace::default(),
+        Some(name) => ActionSpace::from_name(name).ok_or(format!("Invalid actions {}", name))?,
+    };
+    let encoder = match flag_value(args, "--inputs") {
+        //turning relative to the heading goes with sensors relative to the heading
+        None if actions == ActionSpace::Relative => EncoderKind::Relative,
+        None => EncoderKind::default(),
+        Some(name) => EncoderKind::from_name(name).ok_or(format!("Invalid inputs {}", name))?,
+    };
+    Ok(GenerationHeader{encoder, actions})
+}
+//the generation files given to battle, up to the first flag
+fn battle_files(args: &[String]) -> Vec<&str> {
+    args[2..]
+        .iter()
+        .take_while(|arg| !arg.starts_with("--"))
+        .map(|arg| arg.as_str())
+        .collect()
+}
+/// The inputs and actions the networks in every file were trained with, they have to agree to share a board
+fn header_of_files(files: &[&str]) -> Result<GenerationHeader, String> {
+    let Some(first) = files.first() else {
+        return Err("battle needs at least one generation file".to_string());
+    };
+    let header = saved_header(first)?;
+    for file in &files[1..] {
+        let other = saved_header(file)?;
+        if other != header {
+            return Err(format!("{} uses {} but {} uses {}", first, header, file, other));
+        }
+    }
+    Ok(header)
+}
+fn saved_header(path: &str) -> Result<GenerationHeader, String> {
+    let string = std::fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+    serde_json::from_str(&string).map_err(|err| format!("invalid generation {}: {}", path, err))
+}
+/// Reads `--reward FRUIT:DEATH:STEP:DISTANCE`
+fn reward_shaping_from_args(args: &[String]) -> Result<Option<RewardShaping>, String> {
+    let Some(value) = flag_value(args, "--reward") else {return Ok(None)};
+    let rewards = value
+        .split(':')
+        .map(|reward| reward.parse::<f32>().ok())
+        .collect::<Option<Vec<f32>>>();
+    match rewards.as_deref() {
+        //scores are sorted and summed, NaN or infinity would break both
+        Some(&[fruit, death, step, distance]) if [fruit, death, step, distance].iter().all(|reward| reward.is_finite()) =>
+            Ok(Some(RewardShaping{fruit, death, step, distance})),
+        _ => Err(format!("Invalid reward {}", value)),
+    }
+}
+fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|arg| arg == flag)
+}
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|value| value.as_str())
+}
+
+enum PlayCommand {
+    Move(Direction),
+    //dump the current game so it can be reloaded with --state
+    Save,
+    //take back the last move
+    Undo,
+    //print the board the way a network that sees the whole board gets it
+    Observe,
+    Quit,
+}
+
+fn get_command_from_console() -> PlayCommand {
     loop {
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
+        if std::io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+            return PlayCommand::Quit;
+        }
         match input.trim() {
-            "w" => return Direction::Up,
-            "s" => return Direction::Down,
-            "a" => return Direction::Left,
-            "d" => return Direction::Right,
+            "w" => return PlayCommand::Move(Direction::Up),
+            "s" => return PlayCommand::Move(Direction::Down),
+            "a" => return PlayCommand::Move(Direction::Left),
+            "d" => return PlayCommand::Move(Direction::Right),
+            "p" => return PlayCommand::Save,
+            "u" => return PlayCommand::Undo,
+            "o" => return PlayCommand::Observe,
+            "q" => return PlayCommand::Quit,
             _ => println!("Invalid input"),
         }
     }
 }
 
-fn get_input_from_network(game: &SnakeGame, net: &Network) -> Direction {
-    let input = 
-        ColVector::new_from_slice([
-            [if game.obstacle_direction_up() { 1.0 } else { 0.0 };1],
-            [if game.obstacle_direction_down() { 1.0 } else { 0.0 };1],
-            [if game.obstacle_direction_left() { 1.0 } else { 0.0 };1],
-            [if game.obstacle_direction_right() { 1.0 } else { 0.0 };1],
-            [if game.current_direction_up() { 1.0 } else { 0.0 };1],
-            [if game.current_direction_down() { 1.0 } else { 0.0 };1],
-            [if game.current_direction_right() { 1.0 } else { 0.0 };1],
-            [if game.current_direction_left() { 1.0 } else { 0.0 };1],
-            [if game.food_direction_up() { 1.0 } else { 0.0 };1],
-            [if game.food_direction_down() { 1.0 } else { 0.0 };1],
-            [if game.food_direction_right() { 1.0 } else { 0.0 };1],
-            [if game.food_direction_left() { 1.0 } else { 0.0 };1],
-        ]);
-
-    let out = match net.choice_with_highest_confidence(input) {
-        0 => Direction::Up,
-        1 => Direction::Down,
-        2 => Direction::Left,
-        3 => Direction::Right,
-        _ => panic!("Invalid output from network"),
-    };
-    out
+//steers around poison as well, eating it never helps
+fn get_greedy_input(game: &impl SnakeSensors) -> Direction {
+    let options = [Direction::Up, Direction::Down, Direction::Left, Direction::Right]
+        .map(|direction| (direction, game.food_in(direction), game.obstacle_in(direction) || game.poison_in(direction)));
+
+    options.iter()
+        .find(|(_, food, obstacle)| *food && !*obstacle)
+        .or_else(|| options.iter().find(|(_, _, obstacle)| !*obstacle))
+        .map(|(direction, _, _)| *direction)
+        .unwrap_or(game.current_direction())
+}
+
+fn get_input_from_network<B: Brain>(game: &impl SnakeSensors, net: &B, encoder: &dyn Encoder, actions: ActionSpace) -> Direction {
+    debug_assert_eq!(encoder.num_inputs(), net.topology().layer_sizes[0], "the encoder doesn't match the network");
+    get_input_from_observation(&encoder.encode(game), net, actions, game.current_direction())
+}
+
+fn get_input_from_observation<B: Brain>(observation: &Observation, net: &B, actions: ActionSpace, heading: Direction) -> Direction {
+    debug_assert_eq!(Some(&actions.num_actions()), net.topology().layer_sizes.last(), "the actions don't match the network");
+    actions.direction(net.choice_with_highest_confidence(observation), heading)
 }
\ No newline at end of file
@@ -119,6 +119,9 @@ impl<'de, const ROWS: usize, const COLS: usize> Deserialize<'de> for Matrix<f32,
     {
 
         let vecs = Vec::<Vec<f32>>::deserialize(deserializer)?;
+        if vecs.len() != ROWS || vecs.iter().any(|row| row.len() != COLS) {
+            return Err(serde::de::Error::custom(format!("expected a {}x{} matrix", ROWS, COLS)));
+        }
         Ok(Matrix::from_vecs(vecs))
     }
 }
@@ -0,0 +1,110 @@
+use crate::network::STARTING_VALUE;
+
+//self-adapting step sizes never shrink below this, or they could get stuck at nothing
+const MIN_STEP_SIZE: f32 = 1e-4;
+
+/// How networks are changed when they're copied into the next generation
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Mutation {
+    //chance of each weight and bias changing, every one is decided on its own
+    pub rate: f32,
+    //standard deviation of the gaussian noise added to a changed weight
+    pub sigma: f32,
+    //chance of a changed weight getting a new random value instead of noise
+    pub reset_rate: f32,
+    //every network carries its own sigma, which mutates along with its weights
+    pub adaptive: bool,
+}
+impl Default for Mutation {
+    fn default() -> Self {
+        Self {
+            rate: 0.2,
+            sigma: 0.5,
+            reset_rate: 0.01,
+            adaptive: false,
+        }
+    }
+}
+impl Mutation {
+    /// The sigma to mutate a network of `num_values` weights and biases with, given the one it carries.
+    /// Adaptive step sizes are scaled by exp(N(0, 1) / sqrt(num_values)) so good step sizes get passed on with the weights
+    pub fn step_size(&self, carried: Option<f32>, num_values: usize, rng: &mut impl rand::Rng) -> f32 {
+        if !self.adaptive {
+            return self.sigma;
+        }
+        let learning_rate = 1.0 / (num_values.max(1) as f32).sqrt();
+        let sigma = carried.unwrap_or(self.sigma);
+        (sigma * (learning_rate * gaussian(rng)).exp()).max(MIN_STEP_SIZE)
+    }
+    pub fn mutate_value(&self, value: f32, sigma: f32, rng: &mut impl rand::Rng) -> f32 {
+        if rng.gen::<f32>() >= self.rate {
+            return value;
+        }
+        if rng.gen::<f32>() < self.reset_rate {
+            return rng.gen_range(-STARTING_VALUE..STARTING_VALUE);
+        }
+        value + sigma * gaussian(rng)
+    }
+}
+
+/// A sample from the standard normal distribution, by the Box-Muller transform
+pub fn gaussian(rng: &mut impl rand::Rng) -> f32 {
+    //1 - [0, 1) is never 0, so the log is finite
+    let radius = (-2.0 * (1.0 - rng.gen::<f32>()).ln()).sqrt();
+    let angle = std::f32::consts::TAU * rng.gen::<f32>();
+    radius * angle.cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+
+    #[test]
+    fn gaussian_has_zero_mean_and_unit_deviation() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let samples: Vec<f32> = (0..100_000).map(|_| gaussian(&mut rng)).collect();
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        let variance = samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f32>() / samples.len() as f32;
+        assert!(mean.abs() < 0.02, "mean {}", mean);
+        assert!((variance.sqrt() - 1.0).abs() < 0.02, "deviation {}", variance.sqrt());
+    }
+
+    #[test]
+    fn rate_is_the_share_of_values_that_change() {
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        let mutation = Mutation {rate: 0.3, sigma: 1.0, reset_rate: 0.0, adaptive: false};
+        let changed = (0..100_000)
+            .filter(|_| mutation.mutate_value(0.0, mutation.sigma, &mut rng) != 0.0)
+            .count();
+        assert!((changed as f32 / 100_000.0 - 0.3).abs() < 0.01, "{} changed", changed);
+    }
+
+    #[test]
+    fn reset_gives_a_new_starting_value() {
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        let mutation = Mutation {rate: 1.0, sigma: 0.0, reset_rate: 1.0, adaptive: false};
+        let values: Vec<f32> = (0..1000).map(|_| mutation.mutate_value(100.0, 0.0, &mut rng)).collect();
+        assert!(values.iter().all(|value| (-STARTING_VALUE..STARTING_VALUE).contains(value)));
+        assert!(values.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn fixed_step_size_ignores_the_carried_one() {
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        let mutation = Mutation {adaptive: false, ..Mutation::default()};
+        assert_eq!(mutation.step_size(Some(3.0), 100, &mut rng), mutation.sigma);
+    }
+
+    #[test]
+    fn adaptive_step_size_starts_from_the_carried_one() {
+        let mut rng = ChaCha12Rng::seed_from_u64(4);
+        let mutation = Mutation {adaptive: true, ..Mutation::default()};
+        let step_sizes: Vec<f32> = (0..1000).map(|_| mutation.step_size(Some(3.0), 100, &mut rng)).collect();
+        //exp(N(0, 1) / 10) stays well within a factor of 2
+        assert!(step_sizes.iter().all(|step_size| (1.5..6.0).contains(step_size)));
+        assert!(step_sizes.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+}
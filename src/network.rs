@@ -1,44 +1,76 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use crate::activation::Activation;
+use crate::crossover::{cross_step_sizes, Crossover};
 use crate::matrix::{ColVector, Matrix};
+use crate::mutation::Mutation;
 
-fn sigmoid(x: f32) -> f32 {
-    if x > 0.0 {
-        x
-    } else {
-        x / 100.0
+/// The size of every layer, inputs first and outputs last, and the activation of every layer but the inputs
+#[derive(Clone, PartialEq, Debug)]
+pub struct Topology {
+    pub layer_sizes: Vec<usize>,
+    pub activations: Vec<Activation>,
+}
+impl std::fmt::Display for Topology {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let activations: Vec<String> = self.activations.iter().map(|activation| activation.to_string()).collect();
+        write!(f, "layers {:?} with {}", self.layer_sizes, activations.join(","))
     }
 }
 
+/// Anything the training loop can evolve
+pub trait Brain: Clone + Send + Serialize + DeserializeOwned + 'static {
+    fn new_random(topology: &Topology, rng: &mut impl rand::Rng) -> Self;
+    fn topology(&self) -> Topology;
+    fn choice_with_highest_confidence(&self, input: &[f32]) -> usize;
+    fn mutate(&mut self, mutation: &Mutation, rng: &mut impl rand::Rng);
+    //both parents have the same topology
+    fn crossover(&self, other: &Self, crossover: Crossover, rng: &mut impl rand::Rng) -> Self;
+}
+
+pub const HIDDEN_SIZE: usize = 12;
 
+//IN is the number of inputs the encoding gives it, OUT the number of actions it picks from
 #[derive(Clone, Serialize, Deserialize)]
-pub struct Network {
-    first: Layer<12, 12>,
-    second: Layer<12, 4>,
+pub struct Network<const IN: usize, const OUT: usize> {
+    first: Layer<IN, HIDDEN_SIZE>,
+    second: Layer<HIDDEN_SIZE, OUT>,
+    //the sigma it mutates with, only kept when step sizes adapt
+    #[serde(default)]
+    step_size: Option<f32>,
 }
-const STARTING_VALUE: f32 = 6.0;
-const LEARNING_RATE: f32 = 0.5;
-pub type NetInput = ColVector<f32, 12>;
-pub type NetOutput = ColVector<f32, 4>;
+pub const STARTING_VALUE: f32 = 6.0;
+pub type NetInput<const IN: usize> = ColVector<f32, IN>;
+pub type NetOutput<const OUT: usize> = ColVector<f32, OUT>;
 
 
-impl Network{
-    pub fn new(rng: &mut impl rand::Rng) -> Network{
+impl<const IN: usize, const OUT: usize> Network<IN, OUT>{
+    const NUM_VALUES: usize = (IN + 1) * HIDDEN_SIZE + (HIDDEN_SIZE + 1) * OUT;
+    fn forward(&self, input: NetInput<IN>) -> NetOutput<OUT> {
+        let out = self.first.forward(input);
+        self.second.forward(out)
+    }
+}
+impl<const IN: usize, const OUT: usize> Brain for Network<IN, OUT>{
+    fn new_random(topology: &Topology, rng: &mut impl rand::Rng) -> Self {
+        debug_assert_eq!(topology.layer_sizes, [IN, HIDDEN_SIZE, OUT]);
         Network{
-            first: Layer::new(rng),
-            second: Layer::new(rng)
+            first: Layer::new(topology.activations[0], rng),
+            second: Layer::new(topology.activations[1], rng),
+            step_size: None,
         }
     }
-    fn forward(&self, input: NetInput) -> NetOutput {
-        let out = self.first.forward(input);
-        let out = self.second.forward(out);
-        out
+    fn topology(&self) -> Topology {
+        Topology{
+            layer_sizes: vec![IN, HIDDEN_SIZE, OUT],
+            activations: vec![self.first.activation, self.second.activation],
+        }
     }
-    pub fn choice_with_highest_confidence(&self, input: NetInput)->usize{
-        let output = self.forward(input);
-        let mut highest_confidence = -std::f32::INFINITY;
+    fn choice_with_highest_confidence(&self, input: &[f32])->usize{
+        let output = self.forward(ColVector::new_from_slice(std::array::from_fn(|row| [input[row]])));
+        let mut highest_confidence = f32::NEG_INFINITY;
         let mut highest_confidence_index = 0;
-        for i in 0..4{
+        for i in 0..OUT{
             let confidence = *output.get_unchecked(i, 0);
             if confidence > highest_confidence{
                 highest_confidence = confidence;
@@ -47,9 +79,19 @@ impl Network{
         }
         highest_confidence_index
     }
-    pub fn randomly_edit(&mut self, rng: &mut impl rand::Rng) {
-        self.first.randomly_edit(rng);
-        self.first.randomly_edit(rng);
+    fn mutate(&mut self, mutation: &Mutation, rng: &mut impl rand::Rng) {
+        let sigma = mutation.step_size(self.step_size, Self::NUM_VALUES, rng);
+        self.step_size = mutation.adaptive.then_some(sigma);
+        self.first.mutate(mutation, sigma, rng);
+        self.second.mutate(mutation, sigma, rng);
+    }
+    fn crossover(&self, other: &Self, crossover: Crossover, rng: &mut impl rand::Rng) -> Self {
+        let blend = rng.gen();
+        Network{
+            first: self.first.crossover(&other.first, crossover, blend, rng),
+            second: self.second.crossover(&other.second, crossover, blend, rng),
+            step_size: cross_step_sizes(self.step_size, other.step_size),
+        }
     }
 }
 
@@ -59,39 +101,327 @@ impl Network{
 struct Layer<const IN: usize, const OUT: usize> {
     matrix: Matrix<f32, OUT, IN>,
     bias: ColVector<f32, OUT>,
+    #[serde(default)]
+    activation: Activation,
 }
 impl<const IN: usize, const OUT: usize> Layer<IN, OUT> {
-    fn new(rng: &mut impl rand::Rng) -> Self {
+    fn new(activation: Activation, rng: &mut impl rand::Rng) -> Self {
         Layer {
             matrix: Matrix::new_from_generator(
                 |_, _| rng.gen_range(-STARTING_VALUE..STARTING_VALUE)
             ),
             bias: ColVector::new_from_generator(
                 |_, _| rng.gen_range(-STARTING_VALUE..STARTING_VALUE)
-            )
+            ),
+            activation,
         }
     }
     fn forward(&self, input: ColVector<f32, IN>) -> ColVector<f32, OUT> {
-        let mut output = self.matrix.mul(&input);
-        output = output.add(&self.bias);
-        output = ColVector::new_from_generator(|i, _| sigmoid(*output.get_unchecked(i, 0)));
-        output
+        let output = self.matrix.mul(&input).add(&self.bias);
+        let mut values: [f32; OUT] = std::array::from_fn(|i| *output.get_unchecked(i, 0));
+        self.activation.apply(&mut values);
+        ColVector::new_from_generator(|i, _| values[i])
     }
-    fn randomly_edit(&mut self, rng: &mut impl rand::Rng) {
+    fn mutate(&mut self, mutation: &Mutation, sigma: f32, rng: &mut impl rand::Rng) {
         self.matrix = Matrix::new_from_generator(
-            |_, _| {
-                let mut val = *self.matrix.get_unchecked(0, 0);
-                val += rng.gen_range(-LEARNING_RATE..LEARNING_RATE);
-                val
-            }
+            |i, j| mutation.mutate_value(*self.matrix.get_unchecked(i, j), sigma, rng)
         );
         self.bias = ColVector::new_from_generator(
-            |_, _| {
-                let mut val = *self.bias.get_unchecked(0, 0);
-                val += rng.gen_range(-LEARNING_RATE..LEARNING_RATE);
-                val
-            }
+            |i, _| mutation.mutate_value(*self.bias.get_unchecked(i, 0), sigma, rng)
         );
     }
+    //the weights of every neuron followed by its bias
+    fn neurons(&self) -> Vec<Vec<f32>> {
+        self.matrix.to_vecs()
+            .into_iter()
+            .zip(self.bias.to_vecs())
+            .map(|(weights, bias)| weights.into_iter().chain(bias).collect())
+            .collect()
+    }
+    fn crossover(&self, other: &Self, crossover: Crossover, blend: f32, rng: &mut impl rand::Rng) -> Self {
+        let neurons: Vec<Vec<f32>> = self.neurons()
+            .iter()
+            .zip(other.neurons())
+            .map(|(first, second)| crossover.neuron(first, &second, blend, rng))
+            .collect();
+        Layer {
+            matrix: Matrix::new_from_generator(|i, j| neurons[i][j]),
+            bias: ColVector::new_from_generator(|i, _| neurons[i][IN]),
+            activation: self.activation,
+        }
+    }
 }
 
+
+/// Any number of hidden layers of any size, picked at runtime, slower than `Network`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DynNetwork {
+    layers: Vec<DynLayer>,
+    //the sigma it mutates with, only kept when step sizes adapt
+    #[serde(default)]
+    step_size: Option<f32>,
+}
+impl Brain for DynNetwork {
+    fn new_random(topology: &Topology, rng: &mut impl rand::Rng) -> Self {
+        DynNetwork {
+            layers: topology.layer_sizes
+                .windows(2)
+                .zip(&topology.activations)
+                .map(|(sizes, activation)| DynLayer::new(sizes[0], sizes[1], *activation, rng))
+                .collect(),
+            step_size: None,
+        }
+    }
+    fn topology(&self) -> Topology {
+        let inputs = self.layers.first().map(|layer| layer.num_inputs());
+        Topology{
+            layer_sizes: inputs.into_iter()
+                .chain(self.layers.iter().map(|layer| layer.bias.len()))
+                .collect(),
+            activations: self.layers.iter().map(|layer| layer.activation).collect(),
+        }
+    }
+    fn choice_with_highest_confidence(&self, input: &[f32]) -> usize {
+        let output = self.layers
+            .iter()
+            .fold(input.to_vec(), |values, layer| layer.forward(&values));
+        let mut highest_confidence = f32::NEG_INFINITY;
+        let mut highest_confidence_index = 0;
+        for (i, confidence) in output.into_iter().enumerate() {
+            if confidence > highest_confidence {
+                highest_confidence = confidence;
+                highest_confidence_index = i;
+            }
+        }
+        highest_confidence_index
+    }
+    fn mutate(&mut self, mutation: &Mutation, rng: &mut impl rand::Rng) {
+        let num_values = self.layers.iter().map(|layer| layer.matrix.len() * (layer.num_inputs() + 1)).sum();
+        let sigma = mutation.step_size(self.step_size, num_values, rng);
+        self.step_size = mutation.adaptive.then_some(sigma);
+        for layer in &mut self.layers {
+            layer.mutate(mutation, sigma, rng);
+        }
+    }
+    fn crossover(&self, other: &Self, crossover: Crossover, rng: &mut impl rand::Rng) -> Self {
+        let blend = rng.gen();
+        DynNetwork {
+            layers: self.layers
+                .iter()
+                .zip(&other.layers)
+                .map(|(first, second)| first.crossover(second, crossover, blend, rng))
+                .collect(),
+            step_size: cross_step_sizes(self.step_size, other.step_size),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct DynLayer {
+    //one row of weights per output
+    matrix: Vec<Vec<f32>>,
+    bias: Vec<f32>,
+    #[serde(default)]
+    activation: Activation,
+}
+impl DynLayer {
+    fn new(inputs: usize, outputs: usize, activation: Activation, rng: &mut impl rand::Rng) -> Self {
+        DynLayer {
+            matrix: (0..outputs)
+                .map(|_| (0..inputs).map(|_| rng.gen_range(-STARTING_VALUE..STARTING_VALUE)).collect())
+                .collect(),
+            bias: (0..outputs).map(|_| rng.gen_range(-STARTING_VALUE..STARTING_VALUE)).collect(),
+            activation,
+        }
+    }
+    fn num_inputs(&self) -> usize {
+        self.matrix.first().map_or(0, |row| row.len())
+    }
+    fn forward(&self, input: &[f32]) -> Vec<f32> {
+        let mut output: Vec<f32> = self.matrix
+            .iter()
+            .zip(&self.bias)
+            .map(|(row, bias)| row.iter().zip(input).map(|(weight, value)| weight * value).sum::<f32>() + bias)
+            .collect();
+        self.activation.apply(&mut output);
+        output
+    }
+    fn mutate(&mut self, mutation: &Mutation, sigma: f32, rng: &mut impl rand::Rng) {
+        for value in self.matrix.iter_mut().flatten().chain(&mut self.bias) {
+            *value = mutation.mutate_value(*value, sigma, rng);
+        }
+    }
+    fn crossover(&self, other: &Self, crossover: Crossover, blend: f32, rng: &mut impl rand::Rng) -> Self {
+        let (matrix, bias) = self.matrix
+            .iter()
+            .zip(&self.bias)
+            .zip(other.matrix.iter().zip(&other.bias))
+            .map(|((first, first_bias), (second, second_bias))| {
+                let first: Vec<f32> = first.iter().chain([first_bias]).copied().collect();
+                let second: Vec<f32> = second.iter().chain([second_bias]).copied().collect();
+                let mut neuron = crossover.neuron(&first, &second, blend, rng);
+                let bias = neuron.pop().unwrap();
+                (neuron, bias)
+            })
+            .unzip();
+        DynLayer {
+            matrix,
+            bias,
+            activation: self.activation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+
+    fn topology(layer_sizes: &[usize]) -> Topology {
+        Topology{
+            layer_sizes: layer_sizes.to_vec(),
+            activations: vec![Activation::default(); layer_sizes.len() - 1],
+        }
+    }
+    //every weight and bias, layer by layer
+    fn values<const IN: usize, const OUT: usize>(network: &Network<IN, OUT>) -> Vec<f32> {
+        let first = network.first.matrix.to_vecs().into_iter().chain(network.first.bias.to_vecs());
+        let second = network.second.matrix.to_vecs().into_iter().chain(network.second.bias.to_vecs());
+        first.chain(second).flatten().collect()
+    }
+    fn dyn_values(network: &DynNetwork) -> Vec<f32> {
+        network.layers
+            .iter()
+            .flat_map(|layer| layer.matrix.iter().flatten().chain(&layer.bias).copied())
+            .collect()
+    }
+
+    #[test]
+    fn every_weight_moves_a_little_from_its_own_value() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut network = Network::<5, 3>::new_random(&topology(&[5, HIDDEN_SIZE, 3]), &mut rng);
+        let before = values(&network);
+        let mutation = Mutation{rate: 1.0, sigma: 0.001, reset_rate: 0.0, adaptive: false};
+        network.mutate(&mutation, &mut rng);
+        let after = values(&network);
+
+        assert_eq!(before.len(), Network::<5, 3>::NUM_VALUES);
+        for (before, after) in before.iter().zip(&after) {
+            assert_ne!(before, after);
+            assert!((before - after).abs() < 0.01, "{} became {}", before, after);
+        }
+    }
+
+    #[test]
+    fn every_weight_gets_its_own_noise() {
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        let mut network = Network::<5, 3>::new_random(&topology(&[5, HIDDEN_SIZE, 3]), &mut rng);
+        let before = values(&network);
+        network.mutate(&Mutation{rate: 1.0, sigma: 1.0, reset_rate: 0.0, adaptive: false}, &mut rng);
+        let mut changes: Vec<f32> = before.iter().zip(values(&network)).map(|(before, after)| after - before).collect();
+        changes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        changes.dedup();
+        assert_eq!(changes.len(), before.len());
+    }
+
+    #[test]
+    fn weights_are_picked_to_mutate_one_at_a_time() {
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        let mut network = Network::<5, 3>::new_random(&topology(&[5, HIDDEN_SIZE, 3]), &mut rng);
+        let before = values(&network);
+        network.mutate(&Mutation{rate: 0.5, sigma: 1.0, reset_rate: 0.0, adaptive: false}, &mut rng);
+        let changed: Vec<bool> = before.iter().zip(values(&network)).map(|(before, after)| *before != after).collect();
+        //both layers are mutated, and neither all or nothing of either
+        let first_layer = (5 + 1) * HIDDEN_SIZE;
+        for layer in [&changed[..first_layer], &changed[first_layer..]] {
+            assert!(layer.contains(&true) && layer.contains(&false));
+        }
+    }
+
+    #[test]
+    fn nothing_changes_at_rate_zero() {
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        let mut network = DynNetwork::new_random(&topology(&[4, 6, 5, 3]), &mut rng);
+        let before = dyn_values(&network);
+        network.mutate(&Mutation{rate: 0.0, ..Mutation::default()}, &mut rng);
+        assert_eq!(before, dyn_values(&network));
+    }
+
+    #[test]
+    fn every_dyn_weight_moves_a_little_from_its_own_value() {
+        let mut rng = ChaCha12Rng::seed_from_u64(4);
+        let mut network = DynNetwork::new_random(&topology(&[4, 6, 5, 3]), &mut rng);
+        let before = dyn_values(&network);
+        network.mutate(&Mutation{rate: 1.0, sigma: 0.001, reset_rate: 0.0, adaptive: false}, &mut rng);
+        let after = dyn_values(&network);
+
+        assert_eq!(before.len(), (4 + 1) * 6 + (6 + 1) * 5 + (5 + 1) * 3);
+        for (before, after) in before.iter().zip(&after) {
+            assert_ne!(before, after);
+            assert!((before - after).abs() < 0.01, "{} became {}", before, after);
+        }
+    }
+
+    #[test]
+    fn uniform_crossover_takes_every_weight_from_either_parent() {
+        let mut rng = ChaCha12Rng::seed_from_u64(6);
+        let first = Network::<5, 3>::new_random(&topology(&[5, HIDDEN_SIZE, 3]), &mut rng);
+        let second = Network::<5, 3>::new_random(&topology(&[5, HIDDEN_SIZE, 3]), &mut rng);
+        let child = values(&first.crossover(&second, Crossover::Uniform, &mut rng));
+        let (first, second) = (values(&first), values(&second));
+
+        assert!((0..child.len()).all(|i| child[i] == first[i] || child[i] == second[i]));
+        assert!(child != first && child != second);
+    }
+
+    #[test]
+    fn row_crossover_takes_whole_neurons() {
+        let mut rng = ChaCha12Rng::seed_from_u64(7);
+        let first = DynNetwork::new_random(&topology(&[4, 6, 5, 3]), &mut rng);
+        let second = DynNetwork::new_random(&topology(&[4, 6, 5, 3]), &mut rng);
+        let child = first.crossover(&second, Crossover::Rows, &mut rng);
+
+        let mut from_first = 0;
+        for ((child, first), second) in child.layers.iter().zip(&first.layers).zip(&second.layers) {
+            for neuron in 0..child.bias.len() {
+                let whole = |parent: &DynLayer| parent.matrix[neuron] == child.matrix[neuron] && parent.bias[neuron] == child.bias[neuron];
+                assert!(whole(first) || whole(second));
+                from_first += whole(first) as usize;
+            }
+        }
+        assert!(from_first > 0 && from_first < 6 + 5 + 3);
+    }
+
+    #[test]
+    fn blend_crossover_lies_between_the_parents() {
+        let mut rng = ChaCha12Rng::seed_from_u64(8);
+        let first = Network::<5, 3>::new_random(&topology(&[5, HIDDEN_SIZE, 3]), &mut rng);
+        let second = Network::<5, 3>::new_random(&topology(&[5, HIDDEN_SIZE, 3]), &mut rng);
+        let child = values(&first.crossover(&second, Crossover::Blend, &mut rng));
+        let (first, second) = (values(&first), values(&second));
+
+        //the same share of the first parent everywhere
+        let share = (child[0] - second[0]) / (first[0] - second[0]);
+        assert!((0.0..=1.0).contains(&share));
+        for i in 0..child.len() {
+            assert!((child[i] - (share * first[i] + (1.0 - share) * second[i])).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn step_size_is_only_kept_when_it_adapts() {
+        let mut rng = ChaCha12Rng::seed_from_u64(5);
+        let mut network = Network::<5, 3>::new_random(&topology(&[5, HIDDEN_SIZE, 3]), &mut rng);
+        network.mutate(&Mutation::default(), &mut rng);
+        assert_eq!(network.step_size, None);
+
+        let adaptive = Mutation{adaptive: true, ..Mutation::default()};
+        network.mutate(&adaptive, &mut rng);
+        let step_size = network.step_size.unwrap();
+        assert_ne!(step_size, adaptive.sigma);
+        network.mutate(&adaptive, &mut rng);
+        assert_ne!(network.step_size, Some(step_size));
+    }
+}
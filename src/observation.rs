@@ -0,0 +1,74 @@
+use std::fmt::Display;
+
+/// One layer of the board as the network sees it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Plane {
+    //1 on the head
+    Head,
+    //1 on the neck down to almost 0 on the last tail, how long each part of the body stays
+    Body,
+    //1 on fruit worth eating, bonus and timed fruit included
+    Fruit,
+    Bonus,
+    Timed,
+    //1 on poison, which the snake can still move onto
+    Poison,
+    //1 on walls, and outside the board when it doesn't wrap
+    Wall,
+}
+pub const NUM_PLANES: usize = 7;
+impl Plane {
+    pub const ALL: [Plane; NUM_PLANES] = [Plane::Head, Plane::Body, Plane::Fruit, Plane::Bonus, Plane::Timed, Plane::Poison, Plane::Wall];
+}
+
+/// A stack of planes over a rectangle of cells, stored plane by plane and row by row
+#[derive(Clone, Debug)]
+pub struct Planes {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
+impl Planes {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            values: vec![0.0; NUM_PLANES * width * height],
+        }
+    }
+    pub fn get(&self, plane: Plane, x: usize, y: usize) -> f32 {
+        self.values[self.index(plane, x, y)]
+    }
+    //plane by plane and row by row
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+    //row by row
+    pub fn plane(&self, plane: Plane) -> &[f32] {
+        let start = self.index(plane, 0, 0);
+        &self.values[start..start + self.width * self.height]
+    }
+    pub(crate) fn set_cell(&mut self, x: usize, y: usize, values: &[f32; NUM_PLANES]) {
+        for (plane, value) in Plane::ALL.iter().zip(values) {
+            let index = self.index(*plane, x, y);
+            self.values[index] = *value;
+        }
+    }
+    fn index(&self, plane: Plane, x: usize, y: usize) -> usize {
+        (plane as usize * self.height + y) * self.width + x
+    }
+}
+impl Display for Planes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for plane in Plane::ALL {
+            writeln!(f, "{:?}:", plane)?;
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    write!(f, "{:5.2} ", self.get(plane, x, y))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use crate::snake_game::{Direction, EndFrameState, GameConfig, SnakeGame};
+
+/// A game stored as the seed and every input, the engine is deterministic so that's enough to play it again
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    config: GameConfig,
+    seed: u64,
+    //one of U D L R per frame
+    inputs: String,
+    //how the game ended when it was recorded, Continue if it was stopped early
+    end: EndFrameState,
+}
+impl Replay {
+    pub fn new(seed: u64, config: &GameConfig) -> Self {
+        Self {
+            config: config.clone(),
+            seed,
+            inputs: String::new(),
+            end: EndFrameState::Continue,
+        }
+    }
+    pub fn record(&mut self, input: Direction) {
+        self.inputs.push(match input {
+            Direction::Up => 'U',
+            Direction::Down => 'D',
+            Direction::Left => 'L',
+            Direction::Right => 'R',
+        });
+    }
+    //forgets the last frames, for games where moves were taken back
+    pub fn undo(&mut self, frames: usize) {
+        let keep = self.inputs.len().saturating_sub(frames);
+        self.inputs.truncate(keep);
+        self.end = EndFrameState::Continue;
+    }
+    pub fn finish(&mut self, end: EndFrameState) {
+        self.end = end;
+    }
+    pub fn end(&self) -> EndFrameState {
+        self.end
+    }
+    /// Plays the inputs again, returning the game before the first input and after every one of them,
+    /// and how it ended
+    pub fn simulate(&self) -> (Vec<SnakeGame>, EndFrameState) {
+        let mut game = SnakeGame::new(self.seed, &self.config);
+        let mut frames = vec![game.clone()];
+        let mut end = EndFrameState::Continue;
+        for input in self.inputs.chars().filter_map(parse_input) {
+            game.accept_input(input);
+            end = game.to_next_frame();
+            frames.push(game.clone());
+        }
+        (frames, end)
+    }
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let string = serde_json::to_string(self).map_err(|err| format!("failed to serialize replay: {}", err))?;
+        std::fs::write(path, string).map_err(|err| format!("failed to write {}: {}", path, err))
+    }
+    pub fn load(path: &str) -> Result<Self, String> {
+        let string = std::fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+        let replay: Self = serde_json::from_str(&string).map_err(|err| format!("invalid replay {}: {}", path, err))?;
+        if let Some(input) = replay.inputs.chars().find(|input| parse_input(*input).is_none()) {
+            return Err(format!("invalid replay {}: unknown input {}", path, input));
+        }
+        Ok(replay)
+    }
+}
+
+fn parse_input(input: char) -> Option<Direction> {
+    match input {
+        'U' => Some(Direction::Up),
+        'D' => Some(Direction::Down),
+        'L' => Some(Direction::Left),
+        'R' => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snake_game::SpecialFruits;
+
+    #[test]
+    fn the_seed_and_inputs_play_the_same_game_again() {
+        let config = GameConfig {
+            fruit_count: 2,
+            special_fruits: SpecialFruits {bonus_chance: 0.2, poison_chance: 0.2, timed_chance: 0.2, ..SpecialFruits::default()},
+            ..GameConfig::default()
+        };
+        let mut game = SnakeGame::new(3, &config);
+        let mut replay = Replay::new(3, &config);
+        let mut played = vec![game.to_string()];
+        while !game.is_over() {
+            let input = crate::get_greedy_input(&game);
+            game.accept_input(input);
+            replay.record(input);
+            replay.finish(game.to_next_frame());
+            played.push(game.to_string());
+        }
+
+        let (frames, end) = replay.simulate();
+        assert_eq!(end, replay.end());
+        assert!(end.is_over());
+        //every frame is drawn the same, the snake, every fruit and what kind it is
+        assert_eq!(frames.iter().map(|frame| frame.to_string()).collect::<Vec<String>>(), played);
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+/// How the parents of every new network are picked
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SelectionStrategy {
+    //the best of this many networks picked at random
+    Tournament{size: usize},
+    //chance in proportion to the score above the worst one
+    Roulette,
+    //chance in proportion to the place from the bottom, the worst of N gets 1 and the best N
+    Rank,
+    //only this share of the best networks breed, all with the same chance
+    Truncation{share: f32},
+}
+impl SelectionStrategy {
+    /// `tournament[:SIZE]`, `roulette`, `rank` or `truncation[:SHARE]`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.split_once(':') {
+            None => match name {
+                "tournament" => Some(SelectionStrategy::Tournament{size: 3}),
+                "roulette" => Some(SelectionStrategy::Roulette),
+                "rank" => Some(SelectionStrategy::Rank),
+                "truncation" => Some(SelectionStrategy::Truncation{share: 0.2}),
+                _ => None,
+            },
+            Some(("tournament", size)) => size.parse().ok()
+                .filter(|size| *size > 0)
+                .map(|size| SelectionStrategy::Tournament{size}),
+            Some(("truncation", share)) => share.parse().ok()
+                .filter(|share| *share > 0.0 && *share <= 1.0)
+                .map(|share| SelectionStrategy::Truncation{share}),
+            _ => None,
+        }
+    }
+    /// The index of a parent, `scores` are sorted best first and can't be empty
+    pub fn pick(&self, scores: &[f32], rng: &mut impl rand::Rng) -> usize {
+        match *self {
+            SelectionStrategy::Tournament{size} => (0..size)
+                .map(|_| rng.gen_range(0..scores.len()))
+                .min()
+                .unwrap_or(0),
+            SelectionStrategy::Roulette => {
+                //scores can be negative when they're rewards
+                let worst = scores.iter().copied().fold(f32::INFINITY, f32::min);
+                let total: f32 = scores.iter().map(|score| score - worst).sum();
+                if total <= 0.0 {
+                    return rng.gen_range(0..scores.len());
+                }
+                let mut ticket = rng.gen_range(0.0..total);
+                for (i, score) in scores.iter().enumerate() {
+                    ticket -= score - worst;
+                    if ticket < 0.0 {
+                        return i;
+                    }
+                }
+                scores.len() - 1
+            },
+            SelectionStrategy::Rank => {
+                let n = scores.len();
+                let mut ticket = rng.gen_range(0..n * (n + 1) / 2);
+                for i in 0..n {
+                    let weight = n - i;
+                    if ticket < weight {
+                        return i;
+                    }
+                    ticket -= weight;
+                }
+                n - 1
+            },
+            SelectionStrategy::Truncation{share} => {
+                let kept = ((scores.len() as f32 * share).ceil() as usize).clamp(1, scores.len());
+                rng.gen_range(0..kept)
+            },
+        }
+    }
+}
+
+/// How the next generation is picked out of the last one, saved with the generation
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Selection {
+    pub strategy: SelectionStrategy,
+    //this many of the best networks go on unchanged
+    pub elites: usize,
+}
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            strategy: SelectionStrategy::Tournament{size: 3},
+            elites: 1,
+        }
+    }
+}
+impl std::fmt::Display for Selection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.strategy {
+            SelectionStrategy::Tournament{size} => write!(f, "tournament of {}", size)?,
+            SelectionStrategy::Roulette => write!(f, "roulette")?,
+            SelectionStrategy::Rank => write!(f, "rank")?,
+            SelectionStrategy::Truncation{share} => write!(f, "best {}%", share * 100.0)?,
+        }
+        write!(f, ", {} elites", self.elites)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+
+    const SCORES: [f32; 5] = [10.0, 6.0, 3.0, 1.0, 1.0];
+
+    fn picks(strategy: SelectionStrategy, scores: &[f32]) -> Vec<usize> {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut picks = vec![0; scores.len()];
+        for _ in 0..100_000 {
+            picks[strategy.pick(scores, &mut rng)] += 1;
+        }
+        picks
+    }
+
+    #[test]
+    fn tournament_of_one_is_uniform() {
+        let picks = picks(SelectionStrategy::Tournament{size: 1}, &SCORES);
+        assert!(picks.iter().all(|picks| (19_000..21_000).contains(picks)), "{:?}", picks);
+    }
+
+    #[test]
+    fn bigger_tournaments_favour_the_best() {
+        let picks = picks(SelectionStrategy::Tournament{size: 3}, &SCORES);
+        assert!(picks.windows(2).all(|pair| pair[0] > pair[1]), "{:?}", picks);
+    }
+
+    #[test]
+    fn roulette_follows_the_score_above_the_worst() {
+        let picks = picks(SelectionStrategy::Roulette, &SCORES);
+        //9 : 5 : 2 : 0 : 0
+        assert!((55_000..57_500).contains(&picks[0]), "{:?}", picks);
+        assert!((30_000..32_500).contains(&picks[1]), "{:?}", picks);
+        assert_eq!(&picks[3..], [0, 0]);
+    }
+
+    #[test]
+    fn roulette_with_equal_scores_is_uniform() {
+        let picks = picks(SelectionStrategy::Roulette, &[-2.0; 4]);
+        assert!(picks.iter().all(|picks| (24_000..26_000).contains(picks)), "{:?}", picks);
+    }
+
+    #[test]
+    fn rank_ignores_how_far_apart_the_scores_are() {
+        let picks = picks(SelectionStrategy::Rank, &SCORES);
+        //5 : 4 : 3 : 2 : 1 out of 15
+        for (i, picks) in picks.iter().enumerate() {
+            let expected = 100_000 * (5 - i) / 15;
+            assert!(picks.abs_diff(expected) < 1000, "{} picked {} times", i, picks);
+        }
+    }
+
+    #[test]
+    fn truncation_only_picks_the_best() {
+        let picks = picks(SelectionStrategy::Truncation{share: 0.4}, &SCORES);
+        assert!(picks[0] > 0 && picks[1] > 0);
+        assert_eq!(&picks[2..], [0, 0, 0]);
+    }
+}
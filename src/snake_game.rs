@@ -1,100 +1,362 @@
-use std::fmt::Display;
+use std::{collections::VecDeque, fmt::Display};
 use rand::{
-    rngs::StdRng,
     seq::IteratorRandom,
+    Rng,
     SeedableRng
 };
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
+use crate::observation::{Plane, Planes, NUM_PLANES};
+
+pub type Length = u32;
+
+//games that outlast this many frames end in a draw by default, so a snake that can't starve can't go round forever
+pub const DEFAULT_MAX_FRAMES: Length = 10_000;
+fn default_max_frames() -> Length {
+    DEFAULT_MAX_FRAMES
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CellState {
     #[default]
     Empty,
     Head,
-    Tail{life: u8},
-    Fruit,
+    Tail{life: Length},
+    Fruit(FruitKind),
+    Wall,
+}
+impl CellState {
+    //poison isn't in the way, it has sensors of its own
+    fn is_obstacle(&self) -> bool {
+        matches!(self, CellState::Head | CellState::Tail{..} | CellState::Wall)
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FruitKind {
+    Normal,
+    //grows the snake by SpecialFruits::bonus_growth
+    Bonus,
+    Poison,
+    //a normal fruit that disappears after SpecialFruits::lifetime frames
+    Timed,
+}
+impl Display for CellState{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            CellState::Empty => ". ",
+            CellState::Head => "H ",
+            CellState::Tail{..} => "T ",
+            CellState::Fruit(FruitKind::Normal) => "F ",
+            CellState::Fruit(FruitKind::Bonus) => "B ",
+            CellState::Fruit(FruitKind::Poison) => "P ",
+            CellState::Fruit(FruitKind::Timed) => "f ",
+            CellState::Wall => "# ",
+        })
+    }
+}
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameConfig {
+    pub width: i32,
+    pub height: i32,
+    //leaving one edge of the board enters on the opposite edge
+    pub wrap: bool,
+    pub walls: Vec<(i32, i32)>,
+    pub fruits: Vec<(i32, i32)>,
+    pub start: Option<(i32, i32)>,
+    pub hunger: HungerRule,
+    //when false, inputs that would turn the snake back into its own neck are ignored
+    pub allow_reversal: bool,
+    //how many fruits are kept on the board at once
+    pub fruit_count: usize,
+    pub fruit_spawn: FruitSpawn,
+    pub special_fruits: SpecialFruits,
+    //every snake still alive after this many frames has timed out
+    #[serde(default = "default_max_frames")]
+    pub max_frames: Length,
+}
+impl GameConfig {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            wrap: false,
+            walls: Vec::new(),
+            fruits: Vec::new(),
+            start: None,
+            hunger: HungerRule::default(),
+            allow_reversal: false,
+            fruit_count: 1,
+            fruit_spawn: FruitSpawn::Uniform,
+            special_fruits: SpecialFruits::default(),
+            max_frames: DEFAULT_MAX_FRAMES,
+        }
+    }
+    fn size(&self) -> GridSize {
+        GridSize {width: self.width, height: self.height}
+    }
+    //middle of the board unless set, rounded up and to the left
+    pub fn start_position(&self) -> (i32, i32) {
+        self.start.unwrap_or((self.width / 2, (self.height - 1) / 2))
+    }
+    fn start_point(&self) -> Option<GridPoint> {
+        let (x, y) = self.start_position();
+        GridPoint::from_point(x, y, self.size())
+    }
+    //a single snake starts at the start position, several are spread down the middle column,
+    //each moved forward to the next free cell if its spot is a wall or taken
+    pub(crate) fn start_points(&self, num_snakes: usize) -> Vec<GridPoint> {
+        if num_snakes == 1 {
+            return self.start_point().into_iter().collect();
+        }
+
+        let size = self.size();
+        let mut starts: Vec<GridPoint> = Vec::new();
+        for i in 0..num_snakes {
+            let y = (i as i32 + 1) * self.height / (num_snakes as i32 + 1);
+            let Some(wanted) = GridPoint::from_point(self.width / 2, y, size) else {continue};
+            let free = (0..size.area())
+                .filter_map(|offset| GridPoint::from_index((wanted.to_index() + offset) % size.area(), size))
+                .find(|point| !self.walls.contains(&point.to_point(size)) && !starts.contains(point));
+            starts.extend(free);
+        }
+        starts
+    }
+}
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self::new(10, 10)
+    }
+}
+//the snake starves once it has gone more than base + per_length * length frames
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum HungerRule {
+    Unlimited,
+    TotalFrames{base: Length, per_length: Length},
+    FramesSinceFruit{base: Length, per_length: Length},
+}
+impl HungerRule {
+    pub(crate) fn is_starving(&self, total_frames: Length, frames_since_fruit: Length, length: Length) -> bool {
+        match *self {
+            HungerRule::Unlimited => false,
+            HungerRule::TotalFrames{base, per_length} =>
+                total_frames > base.saturating_add(per_length.saturating_mul(length)),
+            HungerRule::FramesSinceFruit{base, per_length} =>
+                frames_since_fruit > base.saturating_add(per_length.saturating_mul(length)),
+        }
+    }
+}
+impl Default for HungerRule {
+    fn default() -> Self {
+        HungerRule::TotalFrames{base: 200, per_length: 50}
+    }
+}
+//every spawned fruit rolls its kind, anything left over after the chances is a normal fruit
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SpecialFruits {
+    pub bonus_chance: f32,
+    pub poison_chance: f32,
+    pub timed_chance: f32,
+    pub bonus_growth: Length,
+    pub poison: PoisonEffect,
+    //frames before poison and timed fruits disappear
+    pub lifetime: Length,
+}
+impl SpecialFruits {
+    fn roll(&self, rng: &mut impl Rng) -> FruitKind {
+        if self.bonus_chance + self.poison_chance + self.timed_chance <= 0.0 {
+            return FruitKind::Normal;
+        }
+
+        let roll = rng.gen::<f32>();
+        if roll < self.bonus_chance {
+            FruitKind::Bonus
+        } else if roll < self.bonus_chance + self.poison_chance {
+            FruitKind::Poison
+        } else if roll < self.bonus_chance + self.poison_chance + self.timed_chance {
+            FruitKind::Timed
+        } else {
+            FruitKind::Normal
+        }
+    }
+}
+impl Default for SpecialFruits {
+    fn default() -> Self {
+        Self {
+            bonus_chance: 0.0,
+            poison_chance: 0.0,
+            timed_chance: 0.0,
+            bonus_growth: 3,
+            poison: PoisonEffect::Shrink(1),
+            lifetime: 30,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PoisonEffect {
+    //the snake dies if it would shrink below a length of 1
+    Shrink(Length),
+    Kill,
 }
-//10 by 10
-const GRID_SIZE: (i32, i32) = (10, 10);
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FruitSpawn {
+    //any empty cell
+    Uniform,
+    //any of the empty cells furthest from the head
+    FarFromHead,
+    //cycles through the points in order, skipping the ones that are taken
+    Sequence(Vec<(i32, i32)>),
+}
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GridSize {
+    width: i32,
+    height: i32,
+}
+impl GridSize {
+    fn area(&self) -> i32 {
+        self.width * self.height
+    }
+}
+//only holds the board itself, the snake is tracked by Game
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Grid {
-    cells: [CellState; GRID_SIZE.0 as usize * GRID_SIZE.1 as usize],
+    size: GridSize,
+    wrap: bool,
+    cells: Vec<CellState>,
 }
 impl Grid {
-    fn new() -> Self {
-        let mut cells = [CellState::Empty; GRID_SIZE.0 as usize * GRID_SIZE.1 as usize];
-        cells[45] = CellState::Head;
-        Self {cells}
+    fn new(config: &GameConfig) -> Self {
+        let size = config.size();
+        let mut grid = Self {
+            size,
+            wrap: config.wrap,
+            cells: vec![CellState::Empty; size.area().max(0) as usize],
+        };
+        for (x, y) in config.walls.iter() {
+            if let Some(wall) = GridPoint::from_point(*x, *y, size) {
+                grid.set(&wall, CellState::Wall);
+            }
+        }
+        for (x, y) in config.fruits.iter() {
+            if let Some(fruit) = GridPoint::from_point(*x, *y, size) {
+                grid.set(&fruit, CellState::Fruit(FruitKind::Normal));
+            }
+        }
+        grid
     }
     fn get(&self, point: &GridPoint) -> Option<&CellState> {
         self.cells.get(point.to_index() as usize)
     }
-    fn get_mut(&mut self, point: &GridPoint) -> Option<&mut CellState> {
-        self.cells.get_mut(point.to_index() as usize)
-    }
     fn set(&mut self, point: &GridPoint, state: CellState) {
         if let Some(cell) = self.cells.get_mut(point.to_index() as usize) {
             *cell = state;
         }
     }
-    fn first(&self, cell: CellState) -> Option<GridPoint> {
-        self.all(cell).first().cloned()
+    fn neighbor(&self, point: &GridPoint, direction: &Direction) -> Option<GridPoint> {
+        if self.wrap {
+            Some(point.add_wrapping(direction, self.size))
+        } else {
+            point.add(direction, self.size)
+        }
+    }
+    //shortest (dx, dy) from one point to another, going across the edges if the board wraps
+    fn offset(&self, from: &GridPoint, to: &GridPoint) -> (i32, i32) {
+        let (x1, y1) = from.to_point(self.size);
+        let (x2, y2) = to.to_point(self.size);
+        if self.wrap {
+            (
+                shortest_wrapped(x2 - x1, self.size.width),
+                shortest_wrapped(y2 - y1, self.size.height)
+            )
+        } else {
+            (x2 - x1, y2 - y1)
+        }
     }
     fn all(&self, cell: CellState) -> Vec<GridPoint> {
         self.cells
             .iter()
             .enumerate()
             .filter(|(_,c)| **c == cell)
-            .map(|(i, _)| GridPoint::from_index(i as i32))
-            .flatten()
+            .filter_map(|(i, _)| GridPoint::from_index(i as i32, self.size))
             .collect()
     }
 }
-impl Display for Grid{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for (i, cell) in self.cells.iter().enumerate() {
-            if i % GRID_SIZE.0 as usize == 0 {
-                writeln!(f)?;
-            }
-            write!(f, "{}", match cell {
-                CellState::Empty => ". ",
-                CellState::Head => "H ",
-                CellState::Tail{..} => "T ",
-                CellState::Fruit => "F ",
-            })?;
+//a ray only reports the closest of each thing it passes
+fn see_first(sensor: &mut f32, closeness: f32) {
+    if *sensor == 0.0 {
+        *sensor = closeness;
+    }
+}
+fn shortest_wrapped(delta: i32, length: i32) -> i32 {
+    if delta * 2 > length {
+        delta - length
+    } else if delta * 2 < -length {
+        delta + length
+    } else {
+        delta
+    }
+}
+#[derive(Clone, Serialize, Deserialize)]
+struct BitSet {
+    words: Vec<u64>,
+}
+impl BitSet {
+    fn new(len: usize) -> Self {
+        Self {words: vec![0; len.div_ceil(64)]}
+    }
+    fn contains(&self, point: &GridPoint) -> bool {
+        let index = point.to_index() as usize;
+        self.words.get(index / 64).is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+    fn insert(&mut self, point: &GridPoint) {
+        let index = point.to_index() as usize;
+        if let Some(word) = self.words.get_mut(index / 64) {
+            *word |= 1 << (index % 64);
+        }
+    }
+    fn remove(&mut self, point: &GridPoint) {
+        let index = point.to_index() as usize;
+        if let Some(word) = self.words.get_mut(index / 64) {
+            *word &= !(1 << (index % 64));
         }
-        Ok(())
     }
 }
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GridPoint(i32);
 impl GridPoint {
-    fn from_point(x: i32, y: i32) -> Option<Self> {
-        if x < GRID_SIZE.0 && y < GRID_SIZE.1 && x >= 0 && y >= 0 {
-            Some(Self(x + y * GRID_SIZE.0))
+    fn from_point(x: i32, y: i32, size: GridSize) -> Option<Self> {
+        if x < size.width && y < size.height && x >= 0 && y >= 0 {
+            Some(Self(x + y * size.width))
         } else {
             None
         }
     }
-    fn from_index(index: i32) -> Option<Self> {
-        if index < GRID_SIZE.0 * GRID_SIZE.1 && index >= 0 {
+    fn from_index(index: i32, size: GridSize) -> Option<Self> {
+        if index < size.area() && index >= 0 {
             Some(Self(index))
         } else {
             None
         }
     }
-    fn to_index(&self) -> i32 {
+    fn to_index(self) -> i32 {
         self.0
     }
-    fn to_point(&self) -> (i32, i32) {
-        (self.0 % GRID_SIZE.0, self.0 / GRID_SIZE.0)
+    fn to_point(self, size: GridSize) -> (i32, i32) {
+        (self.0 % size.width, self.0 / size.width)
+    }
+    fn add(&self, direction: &Direction, size: GridSize) -> Option<Self> {
+        let (x1, y1) = self.to_point(size);
+        let (x2, y2) = direction.to_point();
+        Self::from_point(x1 + x2, y1 + y2, size)
     }
-    fn add(&self, direction: &Direction) -> Option<Self> {
-        let (x1, y1) = self.to_point();
+    fn add_wrapping(&self, direction: &Direction, size: GridSize) -> Self {
+        let (x1, y1) = self.to_point(size);
         let (x2, y2) = direction.to_point();
-        Self::from_point(x1 + x2, y1 + y2)
+        Self((x1 + x2).rem_euclid(size.width) + (y1 + y2).rem_euclid(size.height) * size.width)
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -102,7 +364,7 @@ pub enum Direction {
     Right,
 }
 impl Direction {
-    pub fn to_point(&self) -> (i32, i32) {
+    pub fn to_point(self) -> (i32, i32) {
         match self {
             Direction::Up => (0, -1),
             Direction::Down => (0, 1),
@@ -110,234 +372,1184 @@ impl Direction {
             Direction::Right => (1, 0),
         }
     }
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Up,
+        }
+    }
+    pub fn turn_right(self) -> Direction {
+        self.turn_left().opposite()
+    }
+    pub fn opposite(self) -> Direction {
+        self.turn_left().turn_left()
+    }
+}
+//clockwise from up, the diagonals in between
+const RAY_DIRECTIONS: [(i32, i32); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+/// What a ray from the head sees, each value is 1 / distance to the first one of them or 0 if there is none
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct RaySensor {
+    //the edge of the board or a wall cell
+    pub wall: f32,
+    pub body: f32,
+    //any fruit worth eating, bonus and timed fruit included
+    pub fruit: f32,
+    pub bonus: f32,
+    pub timed: f32,
+    //poison is seen through like the other fruit
+    pub poison: f32,
+}
+/// The whole game including the rng, so a clone or a deserialized copy plays on exactly the same way
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SnakeGame{
+    state: GameState,
+    history: History,
+    //what happened in the last frame
+    #[serde(skip)]
+    events: Vec<GameEvent>,
 }
-pub enum SnakeGame{
-    Game(Game),
-    GameOver{score: u8},
+#[derive(Clone, Serialize, Deserialize)]
+enum GameState{
+    Game(Box<Game>),
+    GameOver{score: Length, cause: GameOverCause},
+}
+//the states before the last few frames, oldest first
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct History{
+    limit: usize,
+    states: VecDeque<GameState>,
 }
 impl SnakeGame{
-    pub fn new(seed: u64) -> Self {
-        let rng: StdRng = StdRng::seed_from_u64(seed);
-        Self::Game(Game::new(rng))
+    pub fn new(seed: u64, config: &GameConfig) -> Self {
+        let rng: ChaCha12Rng = ChaCha12Rng::seed_from_u64(seed);
+        Self{
+            state: GameState::Game(Box::new(Game::new(rng, config))),
+            history: History::default(),
+            events: Vec::new(),
+        }
     }
-    pub fn accept_input(&mut self, input: Direction) {
-        match self {
-            Self::Game(game) => game.accept_input(input),
-            Self::GameOver{..} => {},
+    /// Remembers up to `limit` frames so they can be taken back with `rewind`,
+    /// every frame then costs a copy of the game
+    pub fn with_history(mut self, limit: usize) -> Self {
+        self.history.limit = limit;
+        self
+    }
+    //returns false if the input was ignored
+    pub fn accept_input(&mut self, input: Direction) -> bool {
+        match &mut self.state {
+            GameState::Game(game) => game.accept_input(input),
+            GameState::GameOver{..} => false,
         }
     }
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_next_frame(&mut self) -> EndFrameState {
-        match self {
-            Self::Game(game) => {
+        self.events.clear();
+        match &mut self.state {
+            GameState::Game(game) => {
+                if self.history.limit > 0 {
+                    if self.history.states.len() == self.history.limit {
+                        self.history.states.pop_front();
+                    }
+                    self.history.states.push_back(GameState::Game(game.clone()));
+                }
                 let next_frame_out = game.to_next_frame();
-                if let EndFrameState::GameOver{score} = next_frame_out {
-                    *self = Self::GameOver{score};
+                game.board.drain_events_into(&mut self.events);
+                if let EndFrameState::GameOver{score, cause} = next_frame_out {
+                    self.state = GameState::GameOver{score, cause};
                 }
                 next_frame_out
             },
-            Self::GameOver{score} => EndFrameState::GameOver{score: *score},
+            GameState::GameOver{score, cause} => EndFrameState::GameOver{score: *score, cause: *cause},
+        }
+    }
+    pub fn events(&self) -> &[GameEvent] {
+        &self.events
+    }
+    pub fn is_over(&self) -> bool {
+        matches!(self.state, GameState::GameOver{..})
+    }
+    /// Goes back up to `frames` frames, game over included, and returns how many it actually went back
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let frames = frames.min(self.history.states.len());
+        let keep = self.history.states.len() - frames;
+        if let Some(state) = self.history.states.drain(keep..).next() {
+            self.state = state;
+            self.events.clear();
         }
+        frames
     }
     pub fn print_frame(&self) {
         print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
         println!("{}", self);
     }
-    pub fn neighboring_cell(&self, direction: Direction) -> CellState {
-        let SnakeGame::Game(game) = self else {return CellState::Tail { life: 0 }};
-        let head = game.grid.first(CellState::Head).expect("head should exist");
+    pub fn length(&self) -> Length {
+        let GameState::Game(game) = &self.state else {return 0};
+        game.snake.length
+    }
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let string = serde_json::to_string(self).map_err(|err| format!("failed to serialize game: {}", err))?;
+        std::fs::write(path, string).map_err(|err| format!("failed to write {}: {}", path, err))
+    }
+    pub fn load(path: &str) -> Result<Self, String> {
+        let string = std::fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+        serde_json::from_str(&string).map_err(|err| format!("invalid game state {}: {}", path, err))
+    }
+    /// The whole board as planes, empty once the game is over
+    pub fn planes(&self) -> Planes {
+        let GameState::Game(game) = &self.state else {return Planes::new(0, 0)};
+        game.board.planes(&[&game.snake])
+    }
+    /// Steps to the nearest fruit that isn't poison, ignoring obstacles
+    pub fn food_distance(&self) -> Option<Length> {
+        let GameState::Game(game) = &self.state else {return None};
+        game.board.food_distance(&game.snake.head()?)
+    }
+}
+impl SnakeSensors for SnakeGame{
+    fn rays(&self) -> [RaySensor; 8] {
+        let GameState::Game(game) = &self.state else {return [RaySensor::default(); 8]};
+        let Some(head) = game.snake.head() else {return [RaySensor::default(); 8]};
+        game.board.cast_rays(&head)
+    }
+    fn egocentric_planes(&self, radius: usize) -> Planes {
+        let GameState::Game(game) = &self.state else {return Planes::new(2 * radius + 1, 2 * radius + 1)};
+        game.board.egocentric_planes(&[&game.snake], radius)
+    }
+    fn neighboring_cell(&self, direction: Direction) -> CellState {
+        let GameState::Game(game) = &self.state else {return CellState::Wall};
+        let head = game.snake.head().expect("head should exist");
 
-        let Some(neighboring_cell) = head.add(&direction) else {
-            return CellState::Tail { life: 0 };
+        let Some(neighboring_cell) = game.board.grid.neighbor(&head, &direction) else {
+            return CellState::Wall;
         };
 
-        game.grid.get(&neighboring_cell).copied().unwrap_or(CellState::Tail { life: 0 })
-    }
-    pub fn length(&self) -> u8 {
-        let SnakeGame::Game(game) = self else {return 0};
-        game.length
+        game.cell(&neighboring_cell)
     }
-    pub fn current_direction(&self) -> Direction {
-        let SnakeGame::Game(game) = self else {return Direction::Right};
-        game.current_direction
+    fn current_direction(&self) -> Direction {
+        let GameState::Game(game) = &self.state else {return Direction::Right};
+        game.snake.current_direction
     }
-
     fn food_direction(&self) -> Vec<Direction> {
-        let SnakeGame::Game(game) = self else {return Vec::new()};
-        let Some(food_pos) = game.grid.first(CellState::Fruit) else {return Vec::new()};
-        let Some(head_pos) = game.grid.first(CellState::Head) else {return Vec::new()};
-
-        let mut directions = Vec::new();
-        let (hx, hy) = head_pos.to_point();
-        let (fx, fy) = food_pos.to_point();
-        if hx < fx {
-            directions.push(Direction::Right);
-        } else if hx > fx {
-            directions.push(Direction::Left);
-        }
-        if hy < fy {
-            directions.push(Direction::Down);
-        } else if hy > fy {
-            directions.push(Direction::Up);
+        let GameState::Game(game) = &self.state else {return Vec::new()};
+        let Some(head_pos) = game.snake.head() else {return Vec::new()};
+        game.board.food_direction(&head_pos)
+    }
+    fn nearest_fruit_kind(&self) -> Option<FruitKind> {
+        let GameState::Game(game) = &self.state else {return None};
+        game.board.nearest_fruit_kind(&game.snake.head()?)
+    }
+}
+impl Display for SnakeGame{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.state {
+            GameState::Game(game) => write!(f, "{}", game),
+            GameState::GameOver{score, cause: GameOverCause::Victory} => write!(f, "Victory, Score: {}", score),
+            GameState::GameOver{score, cause} => write!(f, "Game Over, {}, Score: {}", cause, score),
         }
+    }
+}
+//what a single snake can sense about the board around it, used as network input
+pub trait SnakeSensors {
+    fn neighboring_cell(&self, direction: Direction) -> CellState;
+    fn current_direction(&self) -> Direction;
+    //towards the nearest fruit that isn't poison
+    fn food_direction(&self) -> Vec<Direction>;
+    //what food_direction points to
+    fn nearest_fruit_kind(&self) -> Option<FruitKind>;
+    //up first then clockwise, see RAY_DIRECTIONS
+    fn rays(&self) -> [RaySensor; 8];
+    /// The cells up to `radius` away from the head, with the head in the middle. The view doesn't turn
+    /// with the snake, up is always up
+    fn egocentric_planes(&self, radius: usize) -> Planes;
 
-        directions
+    fn obstacle_in(&self, direction: Direction) -> bool {
+        self.neighboring_cell(direction).is_obstacle()
+    }
+    fn food_in(&self, direction: Direction) -> bool {
+        self.food_direction().contains(&direction)
+    }
+    fn poison_in(&self, direction: Direction) -> bool {
+        self.neighboring_cell(direction) == CellState::Fruit(FruitKind::Poison)
+    }
+    fn food_is(&self, kind: FruitKind) -> bool {
+        self.nearest_fruit_kind() == Some(kind)
     }
-    
 
-    pub fn obstacle_direction_up(&self) -> bool {
-        matches!(
-            self.neighboring_cell(Direction::Up),
-            CellState::Tail {..}
-        )
+    fn obstacle_direction_up(&self) -> bool {
+        self.neighboring_cell(Direction::Up).is_obstacle()
     }
-    pub fn obstacle_direction_down(&self) -> bool {
-        matches!(
-            self.neighboring_cell(Direction::Down),
-            CellState::Tail {..}
-        )
+    fn obstacle_direction_down(&self) -> bool {
+        self.neighboring_cell(Direction::Down).is_obstacle()
     }
-    pub fn obstacle_direction_right(&self) -> bool {
-        matches!(
-            self.neighboring_cell(Direction::Right),
-            CellState::Tail {..}
-        )
+    fn obstacle_direction_right(&self) -> bool {
+        self.neighboring_cell(Direction::Right).is_obstacle()
     }
-    pub fn obstacle_direction_left(&self) -> bool {
-        matches!(
-            self.neighboring_cell(Direction::Left),
-            CellState::Tail {..}
-        )
+    fn obstacle_direction_left(&self) -> bool {
+        self.neighboring_cell(Direction::Left).is_obstacle()
     }
 
-    pub fn current_direction_up(&self) -> bool {
+    fn current_direction_up(&self) -> bool {
         self.current_direction() == Direction::Up
     }
-    pub fn current_direction_down(&self) -> bool {
+    fn current_direction_down(&self) -> bool {
         self.current_direction() == Direction::Down
     }
-    pub fn current_direction_right(&self) -> bool {
+    fn current_direction_right(&self) -> bool {
         self.current_direction() == Direction::Right
     }
-    pub fn current_direction_left(&self) -> bool {
+    fn current_direction_left(&self) -> bool {
         self.current_direction() == Direction::Left
     }
 
-    pub fn food_direction_up(&self) -> bool {
+    fn food_direction_up(&self) -> bool {
         self.food_direction().contains(&Direction::Up)
     }
-    pub fn food_direction_down(&self) -> bool {
+    fn food_direction_down(&self) -> bool {
         self.food_direction().contains(&Direction::Down)
     }
-    pub fn food_direction_right(&self) -> bool {
+    fn food_direction_right(&self) -> bool {
         self.food_direction().contains(&Direction::Right)
     }
-    pub fn food_direction_left(&self) -> bool {
+    fn food_direction_left(&self) -> bool {
         self.food_direction().contains(&Direction::Left)
     }
 }
-impl Display for SnakeGame{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EndFrameState{
+    Continue,
+    GameOver{score: Length, cause: GameOverCause},
+}
+impl EndFrameState {
+    pub fn is_over(&self) -> bool {
+        matches!(self, EndFrameState::GameOver{..})
+    }
+    pub fn final_score(&self) -> Option<Length> {
         match self {
-            Self::Game(game) => write!(f, "{}", game),
-            Self::GameOver{score} => write!(f, "Game Over, Score: {}", score),
+            EndFrameState::Continue => None,
+            EndFrameState::GameOver{score, ..} => Some(*score),
         }
     }
 }
-#[derive(PartialEq, Eq)]
-pub enum EndFrameState{
-    Continue,
-    GameOver{score: u8},
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum GameOverCause {
+    //the edge of the board or a wall cell
+    HitWall,
+    HitSelf,
+    //another snake's body, or two heads moving into the same cell
+    HitSnake,
+    //ran out of frames before finding fruit
+    Starved,
+    Poisoned,
+    //the snake filled every cell so no fruit could spawn
+    Victory,
+    //still alive after GameConfig::max_frames
+    TimedOut,
+}
+impl Display for GameOverCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            GameOverCause::HitWall => "hit a wall",
+            GameOverCause::HitSelf => "hit itself",
+            GameOverCause::HitSnake => "hit another snake",
+            GameOverCause::Starved => "starved",
+            GameOverCause::Poisoned => "ate poison",
+            GameOverCause::Victory => "filled the board",
+            GameOverCause::TimedOut => "ran out of time",
+        })
+    }
+}
+/// Something that happened during a frame, points are (x, y)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameEvent {
+    Moved{to: (i32, i32), direction: Direction},
+    AteFruit{at: (i32, i32), kind: FruitKind},
+    FruitSpawned{at: (i32, i32), kind: FruitKind},
+    //timed and poison fruit that nobody ate in time
+    FruitExpired{at: (i32, i32)},
+    Grew{length: Length},
+    Shrank{length: Length},
+    GameOver{score: Length, cause: GameOverCause},
 }
+impl Display for GameEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameEvent::Moved{to, direction} => write!(f, "moved {:?} to {:?}", direction, to),
+            GameEvent::AteFruit{at, kind} => write!(f, "ate {:?} fruit at {:?}", kind, at),
+            GameEvent::FruitSpawned{at, kind} => write!(f, "{:?} fruit spawned at {:?}", kind, at),
+            GameEvent::FruitExpired{at} => write!(f, "fruit at {:?} expired", at),
+            GameEvent::Grew{length} => write!(f, "grew to {}", length),
+            GameEvent::Shrank{length} => write!(f, "shrank to {}", length),
+            GameEvent::GameOver{score, cause} => write!(f, "game over, {}, score {}", cause, score),
+        }
+    }
+}
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Game {
-    grid: Grid,
-    current_direction: Direction,
-    length: u8, //kill all tails with life >= length
-    rng: StdRng,
+    board: Board,
+    snake: Snake,
+    hunger: HungerRule,
+    allow_reversal: bool,
 }
 impl Game {
-    fn new(rng: StdRng) -> Self {
+    fn new(rng: ChaCha12Rng, config: &GameConfig) -> Self {
+        let start = config.start_point();
+        let mut board = Board::new(rng, config, &start.into_iter().collect::<Vec<GridPoint>>());
+        let snake = Snake::new(start, &mut board);
+
         Self {
-            grid: Grid::new(),
-            current_direction: Direction::Right,
-            length: 1,
-            rng
+            board,
+            snake,
+            hunger: config.hunger,
+            allow_reversal: config.allow_reversal,
         }
     }
-    fn accept_input(&mut self, input: Direction) {
-        self.current_direction = input;
+    fn cell(&self, point: &GridPoint) -> CellState {
+        if self.board.occupied.contains(point) {
+            self.snake.cell(point).expect("occupied cells should be part of the body")
+        } else {
+            self.board.cell(point)
+        }
     }
+    fn accept_input(&mut self, input: Direction) -> bool {
+        self.snake.accept_input(input, self.allow_reversal, &self.board)
+    }
+    #[allow(clippy::wrong_self_convention)]
     fn to_next_frame(&mut self)->EndFrameState{
-        self.kill_tails();
-        self.increase_life();
+        self.snake.kill_tails(&mut self.board);
         let move_head_out = self.move_head();
-        if matches!(move_head_out, EndFrameState::GameOver{..}) {
+        if move_head_out.is_over() {
             return move_head_out;
         }
 
-        if self.count_fruits() == 0 {
-            self.spawn_fruit(1);
+        let heads: Vec<GridPoint> = self.snake.head().into_iter().collect();
+        if !self.board.refill_fruits(&heads) {
+            return self.game_over(GameOverCause::Victory);
+        }
+
+        self.snake.starve();
+        if self.hunger.is_starving(self.board.total_frames, self.snake.frames_since_fruit, self.snake.length) {
+            return self.game_over(GameOverCause::Starved);
+        }
+        if self.board.is_out_of_time() {
+            return self.game_over(GameOverCause::TimedOut);
         }
         EndFrameState::Continue
     }
     fn move_head(&mut self)->EndFrameState{
-        let Some(head_pos) = self.grid.first(CellState::Head) else {return EndFrameState::GameOver{score: self.length}};
-        let Some(new_head_pos) = head_pos.add(&self.current_direction) else {return EndFrameState::GameOver{score: self.length}};
-        
-        match self.grid.get(&new_head_pos) {
-            Some(CellState::Empty) => {
-                self.grid.set(&head_pos, CellState::Tail{life: 0});
-                self.grid.set(&new_head_pos, CellState::Head);
-                EndFrameState::Continue
-            },
-            Some(CellState::Fruit) => {
-                self.grid.set(&head_pos, CellState::Tail{life: 0});
-                self.grid.set(&new_head_pos, CellState::Head);
-                self.length += 1;
-                EndFrameState::Continue
-            },
-            _ => EndFrameState::GameOver{score: self.length},
+        let Some(head_pos) = self.snake.head() else {return self.game_over(GameOverCause::HitSelf)};
+        let new_head_pos = match self.board.target(&head_pos, &self.snake.current_direction) {
+            Ok(new_head_pos) => new_head_pos,
+            Err(cause) => return self.game_over(cause),
+        };
+
+        self.snake.push_head(new_head_pos, &mut self.board);
+        let eaten = self.board.take_fruit(&new_head_pos);
+        if let Some(kind) = eaten {
+            if let Err(cause) = self.snake.eat(kind, &mut self.board) {
+                return self.game_over(cause);
+            }
+        }
+        EndFrameState::Continue
+    }
+    fn game_over(&mut self, cause: GameOverCause) -> EndFrameState {
+        self.board.emit(GameEvent::GameOver{score: self.snake.length, cause});
+        EndFrameState::GameOver{score: self.snake.length, cause}
+    }
+}
+impl Display for Game{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.board.fmt_cells(f, |point| self.cell(point))?;
+        write!(f, "\n{:?}", self.snake.current_direction)
+    }
+}
+//everything but the snakes: walls, fruit, which cells the snakes take up and the rng
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Board {
+    grid: Grid,
+    occupied: BitSet,
+    //in the order they were placed
+    fruits: Vec<Fruit>,
+    fruit_count: usize,
+    fruit_spawn: FruitSpawn,
+    special_fruits: SpecialFruits,
+    //next point to try when spawning from a sequence
+    sequence_index: usize,
+    total_frames: Length,
+    #[serde(default = "default_max_frames")]
+    max_frames: Length,
+    rng: ChaCha12Rng,
+    //what happened since they were last drained, the snakes add theirs too
+    #[serde(skip)]
+    events: Vec<GameEvent>,
+}
+impl Board {
+    //fruit placed on the starting cells is dropped
+    pub(crate) fn new(rng: ChaCha12Rng, config: &GameConfig, starts: &[GridPoint]) -> Self {
+        let mut grid = Grid::new(config);
+        for start in starts {
+            grid.set(start, CellState::Empty);
+        }
+
+        Self {
+            fruits: grid.all(CellState::Fruit(FruitKind::Normal))
+                .into_iter()
+                .map(|point| Fruit{point, expires_at: None})
+                .collect(),
+            occupied: BitSet::new(grid.cells.len()),
+            grid,
+            fruit_count: config.fruit_count,
+            fruit_spawn: config.fruit_spawn.clone(),
+            special_fruits: config.special_fruits,
+            sequence_index: 0,
+            total_frames: 0,
+            max_frames: config.max_frames,
+            rng,
+            events: Vec::new(),
+        }
+    }
+    fn emit(&mut self, event: GameEvent) {
+        self.events.push(event);
+    }
+    pub(crate) fn drain_events_into(&mut self, events: &mut Vec<GameEvent>) {
+        events.append(&mut self.events);
+    }
+    fn to_point(&self, point: &GridPoint) -> (i32, i32) {
+        point.to_point(self.grid.size)
+    }
+    pub(crate) fn total_frames(&self) -> Length {
+        self.total_frames
+    }
+    pub(crate) fn is_out_of_time(&self) -> bool {
+        self.total_frames >= self.max_frames
+    }
+    pub(crate) fn neighbor(&self, point: &GridPoint, direction: &Direction) -> Option<GridPoint> {
+        self.grid.neighbor(point, direction)
+    }
+    pub(crate) fn is_occupied(&self, point: &GridPoint) -> bool {
+        self.occupied.contains(point)
+    }
+    //what's on the cell ignoring the snakes
+    pub(crate) fn cell(&self, point: &GridPoint) -> CellState {
+        self.grid.get(point).copied().unwrap_or(CellState::Wall)
+    }
+    //where a head moving in a direction ends up, unless it runs into the edge or a wall
+    pub(crate) fn target(&self, from: &GridPoint, direction: &Direction) -> Result<GridPoint, GameOverCause> {
+        let Some(target) = self.grid.neighbor(from, direction) else {return Err(GameOverCause::HitWall)};
+        if self.occupied.contains(&target) {
+            Err(GameOverCause::HitSelf)
+        } else if self.grid.get(&target) == Some(&CellState::Wall) {
+            Err(GameOverCause::HitWall)
+        } else {
+            Ok(target)
+        }
+    }
+    pub(crate) fn take_fruit(&mut self, point: &GridPoint) -> Option<FruitKind> {
+        let Some(CellState::Fruit(kind)) = self.grid.get(point).copied() else {return None};
+        self.grid.set(point, CellState::Empty);
+        self.fruits.retain(|fruit| fruit.point != *point);
+        self.emit(GameEvent::AteFruit{at: self.to_point(point), kind});
+        Some(kind)
+    }
+    //ends the frame: drops expired fruit and spawns new fruit, false if there's no fruit left and no room for more
+    pub(crate) fn refill_fruits(&mut self, heads: &[GridPoint]) -> bool {
+        self.remove_expired_fruits();
+        if self.fruits.len() < self.fruit_count {
+            self.spawn_fruit(self.fruit_count - self.fruits.len(), heads);
+            if self.fruits.is_empty() {
+                return false;
+            }
+        }
+        self.total_frames = self.total_frames.saturating_add(1);
+        true
+    }
+    pub(crate) fn food_direction(&self, head: &GridPoint) -> Vec<Direction> {
+        let Some(food_pos) = self.nearest_fruit(head) else {return Vec::new()};
+
+        let mut directions = Vec::new();
+        let (dx, dy) = self.grid.offset(head, &food_pos);
+        if dx > 0 {
+            directions.push(Direction::Right);
+        } else if dx < 0 {
+            directions.push(Direction::Left);
+        }
+        if dy > 0 {
+            directions.push(Direction::Down);
+        } else if dy < 0 {
+            directions.push(Direction::Up);
         }
+
+        directions
     }
-    fn increase_life(&mut self) {
-        for cell in self.grid.cells.iter_mut() {
-            if let CellState::Tail{life} = cell {
-                *life += 1;
+    //the rays stop at the first wall, on a wrapping board they go round until they get back to the head
+    pub(crate) fn cast_rays(&self, head: &GridPoint) -> [RaySensor; 8] {
+        let size = self.grid.size;
+        let max_distance = size.width.max(size.height);
+        RAY_DIRECTIONS.map(|(dx, dy)| {
+            let mut sensor = RaySensor::default();
+            let (mut x, mut y) = head.to_point(size);
+            for distance in 1..=max_distance {
+                let closeness = 1.0 / distance as f32;
+                x += dx;
+                y += dy;
+                if self.grid.wrap {
+                    x = x.rem_euclid(size.width);
+                    y = y.rem_euclid(size.height);
+                }
+                let Some(point) = GridPoint::from_point(x, y, size) else {
+                    sensor.wall = closeness;
+                    break;
+                };
+                if point == *head {
+                    break;
+                }
+                match self.cell(&point) {
+                    CellState::Wall => {
+                        sensor.wall = closeness;
+                        break;
+                    },
+                    CellState::Fruit(FruitKind::Poison) => see_first(&mut sensor.poison, closeness),
+                    CellState::Fruit(kind) => {
+                        see_first(&mut sensor.fruit, closeness);
+                        match kind {
+                            FruitKind::Bonus => see_first(&mut sensor.bonus, closeness),
+                            FruitKind::Timed => see_first(&mut sensor.timed, closeness),
+                            _ => {},
+                        }
+                    },
+                    _ => {},
+                }
+                if self.occupied.contains(&point) {
+                    see_first(&mut sensor.body, closeness);
+                }
             }
+            sensor
+        })
+    }
+    //the value of every plane on every cell, indexed like the grid. The first snake is the one looking,
+    //the heads of the others count as body
+    fn cell_planes(&self, snakes: &[&Snake]) -> Vec<[f32; NUM_PLANES]> {
+        let mut cells: Vec<[f32; NUM_PLANES]> = self.grid.cells
+            .iter()
+            .map(|cell| {
+                let mut values = [0.0; NUM_PLANES];
+                match cell {
+                    CellState::Wall => values[Plane::Wall as usize] = 1.0,
+                    CellState::Fruit(FruitKind::Poison) => values[Plane::Poison as usize] = 1.0,
+                    CellState::Fruit(kind) => {
+                        values[Plane::Fruit as usize] = 1.0;
+                        match kind {
+                            FruitKind::Bonus => values[Plane::Bonus as usize] = 1.0,
+                            FruitKind::Timed => values[Plane::Timed as usize] = 1.0,
+                            _ => {},
+                        }
+                    },
+                    _ => {},
+                }
+                values
+            })
+            .collect();
+
+        for (snake_index, snake) in snakes.iter().enumerate() {
+            let num_tails = snake.body.len().saturating_sub(1).max(1) as f32;
+            for (i, point) in snake.body.iter().enumerate() {
+                let values = &mut cells[point.to_index() as usize];
+                if i == 0 && snake_index == 0 {
+                    values[Plane::Head as usize] = 1.0;
+                } else if i == 0 {
+                    values[Plane::Body as usize] = 1.0;
+                } else {
+                    //the same life a Tail cell has
+                    let life = (i - 1) as f32;
+                    values[Plane::Body as usize] = (num_tails - life) / num_tails;
+                }
+            }
+        }
+        cells
+    }
+    pub(crate) fn planes(&self, snakes: &[&Snake]) -> Planes {
+        let size = self.grid.size;
+        let mut planes = Planes::new(size.width as usize, size.height as usize);
+        for (index, values) in self.cell_planes(snakes).iter().enumerate() {
+            let (x, y) = GridPoint(index as i32).to_point(size);
+            planes.set_cell(x as usize, y as usize, values);
         }
+        planes
     }
-    fn kill_tails(&mut self) {
-        for cell in self.grid.cells.iter_mut() {
-            if let CellState::Tail{life} = cell {
-                if *life >= self.length {
-                    *cell = CellState::Empty;
+    //the crop around the head of the first snake
+    pub(crate) fn egocentric_planes(&self, snakes: &[&Snake], radius: usize) -> Planes {
+        let side = 2 * radius + 1;
+        let mut planes = Planes::new(side, side);
+        let Some(head) = snakes.first().and_then(|snake| snake.head()) else {return planes};
+
+        let size = self.grid.size;
+        let (head_x, head_y) = head.to_point(size);
+        let cells = self.cell_planes(snakes);
+        let mut outside = [0.0; NUM_PLANES];
+        outside[Plane::Wall as usize] = 1.0;
+        for crop_y in 0..side {
+            for crop_x in 0..side {
+                let mut x = head_x + crop_x as i32 - radius as i32;
+                let mut y = head_y + crop_y as i32 - radius as i32;
+                if self.grid.wrap {
+                    x = x.rem_euclid(size.width);
+                    y = y.rem_euclid(size.height);
                 }
+                let values = match GridPoint::from_point(x, y, size) {
+                    Some(point) => &cells[point.to_index() as usize],
+                    None => &outside,
+                };
+                planes.set_cell(crop_x, crop_y, values);
             }
         }
+        planes
+    }
+    pub(crate) fn nearest_fruit_kind(&self, head: &GridPoint) -> Option<FruitKind> {
+        match self.cell(&self.nearest_fruit(head)?) {
+            CellState::Fruit(kind) => Some(kind),
+            _ => None,
+        }
     }
-    fn count_fruits(&self) -> u8 {
-        self.grid.cells.iter().filter(|c| **c == CellState::Fruit).count() as u8
+    pub(crate) fn food_distance(&self, head: &GridPoint) -> Option<Length> {
+        self.nearest_fruit(head).map(|food_pos| self.distance(head, &food_pos) as Length)
     }
-    fn spawn_fruit(&mut self, amount: u8) {
-        let all_empty_cells: Vec<GridPoint> = self.grid.cells
+    //ignores poison
+    fn nearest_fruit(&self, from: &GridPoint) -> Option<GridPoint> {
+        self.fruits
             .iter()
-            .enumerate()
-            .filter(|(_, cell)| **cell == CellState::Empty)
-            .map(|(pos, _)| GridPoint::from_index(pos as i32)
-            .expect("index should be in bounds because were enumerating over the cells"))
-            .choose_multiple(&mut self.rng, amount as usize);
-
-        for cell in all_empty_cells {
-            if let Some(cell) = self.grid.get_mut(&cell) {
-                *cell = CellState::Fruit;
+            .map(|fruit| fruit.point)
+            .filter(|point| self.grid.get(point) != Some(&CellState::Fruit(FruitKind::Poison)))
+            .min_by_key(|point| self.distance(from, point))
+    }
+    fn distance(&self, from: &GridPoint, to: &GridPoint) -> i32 {
+        let (dx, dy) = self.grid.offset(from, to);
+        dx.abs() + dy.abs()
+    }
+    pub(crate) fn fmt_cells<D: Display>(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        cell: impl Fn(&GridPoint) -> D
+    ) -> std::fmt::Result {
+        for index in 0..self.grid.size.area() {
+            if index % self.grid.size.width == 0 {
+                writeln!(f)?;
             }
+            if let Some(point) = GridPoint::from_index(index, self.grid.size) {
+                write!(f, "{}", cell(&point))?;
+            }
+        }
+        Ok(())
+    }
+    fn remove_expired_fruits(&mut self) {
+        let total_frames = self.total_frames;
+        let grid = &mut self.grid;
+        let events = &mut self.events;
+        self.fruits.retain(|fruit| {
+            let expired = fruit.expires_at.is_some_and(|expires_at| expires_at <= total_frames);
+            if expired {
+                grid.set(&fruit.point, CellState::Empty);
+                events.push(GameEvent::FruitExpired{at: fruit.point.to_point(grid.size)});
+            }
+            !expired
+        });
+    }
+    fn is_empty(&self, point: &GridPoint) -> bool {
+        is_empty(&self.grid, &self.occupied, point)
+    }
+    fn empty_cells(&self) -> impl Iterator<Item = GridPoint> + '_ {
+        empty_cells(&self.grid, &self.occupied)
+    }
+    fn spawn_fruit(&mut self, amount: usize, heads: &[GridPoint]) {
+        let new_fruits = match &self.fruit_spawn {
+            FruitSpawn::Uniform => empty_cells(&self.grid, &self.occupied).choose_multiple(&mut self.rng, amount),
+            FruitSpawn::FarFromHead => self.far_from_head_cells(amount, heads),
+            FruitSpawn::Sequence(sequence) => {
+                let sequence = sequence.clone();
+                self.sequence_cells(&sequence, amount)
+            },
+        };
+
+        for point in new_fruits {
+            let kind = self.special_fruits.roll(&mut self.rng);
+            let expires_at = match kind {
+                FruitKind::Poison | FruitKind::Timed => Some(self.total_frames.saturating_add(self.special_fruits.lifetime)),
+                FruitKind::Normal | FruitKind::Bonus => None,
+            };
+            self.grid.set(&point, CellState::Fruit(kind));
+            self.fruits.push(Fruit{point, expires_at});
+            self.emit(GameEvent::FruitSpawned{at: self.to_point(&point), kind});
         }
     }
+    //furthest from the closest head
+    fn far_from_head_cells(&mut self, amount: usize, heads: &[GridPoint]) -> Vec<GridPoint> {
+        if heads.is_empty() {
+            return Vec::new();
+        }
+        let mut candidates: Vec<(GridPoint, i32)> = self.empty_cells()
+            .map(|pos| {
+                let distance = heads.iter().map(|head| self.distance(head, &pos)).min().unwrap_or(0);
+                (pos, distance)
+            })
+            .collect();
+
+        let mut new_fruits = Vec::new();
+        while new_fruits.len() < amount {
+            let Some(furthest) = candidates.iter().map(|(_, distance)| *distance).max() else {break};
+            let Some(chosen) = candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, distance))| *distance == furthest)
+                .map(|(i, _)| i)
+                .choose(&mut self.rng)
+            else {break};
+            new_fruits.push(candidates.swap_remove(chosen).0);
+        }
+        new_fruits
+    }
+    fn sequence_cells(&mut self, sequence: &[(i32, i32)], amount: usize) -> Vec<GridPoint> {
+        let mut new_fruits: Vec<GridPoint> = Vec::new();
+        let mut tries = 0;
+        while new_fruits.len() < amount && tries < sequence.len() {
+            let (x, y) = sequence[self.sequence_index % sequence.len()];
+            self.sequence_index += 1;
+            tries += 1;
+            if let Some(pos) = GridPoint::from_point(x, y, self.grid.size) {
+                if self.is_empty(&pos) && !new_fruits.contains(&pos) {
+                    new_fruits.push(pos);
+                    tries = 0;
+                }
+            }
+        }
+        //every point in the sequence is taken, fall back to the first empty cells
+        if new_fruits.len() < amount {
+            let missing = amount - new_fruits.len();
+            let fallback: Vec<GridPoint> = self.empty_cells()
+                .filter(|pos| !new_fruits.contains(pos))
+                .take(missing)
+                .collect();
+            new_fruits.extend(fallback);
+        }
+        new_fruits
+    }
 }
-impl Display for Game{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.grid)?;
-        write!(f, "\n{:?}", self.current_direction)
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Snake {
+    //head first, a tail's life is its position behind the head
+    body: VecDeque<GridPoint>,
+    current_direction: Direction,
+    length: Length, //kill all tails with life >= length
+    frames_since_fruit: Length,
+}
+impl Snake {
+    //a snake with no starting point has no body and dies on its first move
+    pub(crate) fn new(start: Option<GridPoint>, board: &mut Board) -> Self {
+        let mut body = VecDeque::new();
+        if let Some(start) = start {
+            body.push_front(start);
+            board.occupied.insert(&start);
+        }
+        Self {
+            body,
+            current_direction: Direction::Right,
+            length: 1,
+            frames_since_fruit: 0,
+        }
+    }
+    pub(crate) fn head(&self) -> Option<GridPoint> {
+        self.body.front().copied()
+    }
+    pub(crate) fn length(&self) -> Length {
+        self.length
+    }
+    pub(crate) fn current_direction(&self) -> Direction {
+        self.current_direction
+    }
+    pub(crate) fn frames_since_fruit(&self) -> Length {
+        self.frames_since_fruit
+    }
+    pub(crate) fn cell(&self, point: &GridPoint) -> Option<CellState> {
+        match self.body.iter().position(|body_point| body_point == point)? {
+            0 => Some(CellState::Head),
+            i => Some(CellState::Tail{life: (i - 1) as Length}),
+        }
+    }
+    //returns false if the input was ignored
+    pub(crate) fn accept_input(&mut self, input: Direction, allow_reversal: bool, board: &Board) -> bool {
+        if !allow_reversal && self.is_reversal(&input, &board.grid) {
+            return false;
+        }
+        self.current_direction = input;
+        true
+    }
+    fn is_reversal(&self, input: &Direction, grid: &Grid) -> bool {
+        let (Some(head), Some(neck)) = (self.body.front(), self.body.get(1)) else {return false};
+        grid.neighbor(head, input) == Some(*neck)
+    }
+    //the head and the tails with life < length survive
+    pub(crate) fn kill_tails(&mut self, board: &mut Board) {
+        self.trim_body(self.length as usize + 1, &mut board.occupied);
+    }
+    pub(crate) fn push_head(&mut self, point: GridPoint, board: &mut Board) {
+        self.body.push_front(point);
+        board.occupied.insert(&point);
+        board.emit(GameEvent::Moved{to: board.to_point(&point), direction: self.current_direction});
+    }
+    pub(crate) fn starve(&mut self) {
+        self.frames_since_fruit = self.frames_since_fruit.saturating_add(1);
     }
+    pub(crate) fn eat(&mut self, kind: FruitKind, board: &mut Board) -> Result<(), GameOverCause> {
+        let growth = match kind {
+            FruitKind::Normal | FruitKind::Timed => 1,
+            FruitKind::Bonus => board.special_fruits.bonus_growth,
+            FruitKind::Poison => match board.special_fruits.poison {
+                PoisonEffect::Shrink(amount) if amount < self.length => {
+                    self.length -= amount;
+                    //the head, the new tail and the tails with life < length
+                    self.trim_body(self.length as usize + 2, &mut board.occupied);
+                    board.emit(GameEvent::Shrank{length: self.length});
+                    return Ok(());
+                },
+                _ => return Err(GameOverCause::Poisoned),
+            },
+        };
+        self.length = self.length.saturating_add(growth);
+        self.frames_since_fruit = 0;
+        board.emit(GameEvent::Grew{length: self.length});
+        Ok(())
+    }
+    //frees every cell, used when a snake dies and leaves the board
+    pub(crate) fn clear(&mut self, board: &mut Board) {
+        self.trim_body(0, &mut board.occupied);
+    }
+    fn trim_body(&mut self, max_len: usize, occupied: &mut BitSet) {
+        while self.body.len() > max_len {
+            if let Some(tail) = self.body.pop_back() {
+                occupied.remove(&tail);
+            }
+        }
+    }
+}
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Fruit {
+    point: GridPoint,
+    expires_at: Option<Length>,
+}
+fn is_empty(grid: &Grid, occupied: &BitSet, point: &GridPoint) -> bool {
+    grid.get(point) == Some(&CellState::Empty) && !occupied.contains(point)
+}
+//in index order
+fn empty_cells<'a>(grid: &'a Grid, occupied: &'a BitSet) -> impl Iterator<Item = GridPoint> + 'a {
+    (0..grid.size.area())
+        .map(|pos| GridPoint::from_index(pos, grid.size)
+        .expect("index should be in bounds because were iterating over the grid area"))
+        .filter(|pos| is_empty(grid, occupied, pos))
 }
 
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use Direction::{Down, Left, Right, Up};
+
+    //the snake starts at (5, 4) heading right, two snakes in an arena at (5, 3) and (5, 6).
+    //Fruit eaten off the board respawns out of the way
+    pub(crate) fn config(fruits: &[(i32, i32)]) -> GameConfig {
+        GameConfig {
+            fruits: fruits.to_vec(),
+            fruit_spawn: FruitSpawn::Sequence(vec![(0, 9)]),
+            ..GameConfig::default()
+        }
+    }
+
+    fn play(game: &mut SnakeGame, moves: &[Direction]) -> EndFrameState {
+        let mut end = EndFrameState::Continue;
+        for direction in moves {
+            game.accept_input(*direction);
+            end = game.to_next_frame();
+        }
+        end
+    }
+
+    fn game(game: &SnakeGame) -> &Game {
+        let GameState::Game(game) = &game.state else {panic!("the game is over")};
+        game
+    }
+
+    //head first
+    fn body(snake_game: &SnakeGame) -> Vec<(i32, i32)> {
+        let game = game(snake_game);
+        game.snake.body.iter().map(|point| game.board.to_point(point)).collect()
+    }
+
+    fn fruits(snake_game: &SnakeGame) -> Vec<(i32, i32)> {
+        let game = game(snake_game);
+        game.board.fruits.iter().map(|fruit| game.board.to_point(&fruit.point)).collect()
+    }
+
+    fn assert_occupied_cells_are_the_body(snake_game: &SnakeGame) {
+        let game = game(snake_game);
+        let body = body(snake_game);
+        for index in 0..game.board.grid.size.area() {
+            let point = GridPoint(index);
+            assert_eq!(
+                game.board.occupied.contains(&point),
+                body.contains(&game.board.to_point(&point)),
+                "cell {:?}", game.board.to_point(&point)
+            );
+        }
+    }
+
+    #[test]
+    fn eating_grows_the_tail() {
+        let mut game = SnakeGame::new(0, &config(&[(6, 4)]));
+        assert_eq!(play(&mut game, &[Right]), EndFrameState::Continue);
+        assert_eq!(game.length(), 2);
+        assert_eq!(body(&game), [(6, 4), (5, 4)]);
+        assert_eq!(fruits(&game), [(0, 9)]);
+
+        //the body is the head and up to length + 1 tails, the last one is gone at the start of the next frame
+        play(&mut game, &[Right, Right, Right]);
+        assert_eq!(game.length(), 2);
+        assert_eq!(body(&game), [(9, 4), (8, 4), (7, 4), (6, 4)]);
+        assert_occupied_cells_are_the_body(&game);
+    }
+
+    #[test]
+    fn the_head_can_move_into_the_cell_the_tail_leaves() {
+        let mut game = SnakeGame::new(0, &config(&[(6, 4)]));
+        play(&mut game, &[Right, Down, Left]);
+        assert_eq!(body(&game), [(5, 5), (6, 5), (6, 4), (5, 4)]);
+
+        assert_eq!(play(&mut game, &[Up]), EndFrameState::Continue);
+        assert_eq!(body(&game), [(5, 4), (5, 5), (6, 5), (6, 4)]);
+        assert_occupied_cells_are_the_body(&game);
+    }
+
+    #[test]
+    fn a_snake_that_cant_starve_times_out() {
+        let mut game = SnakeGame::new(0, &GameConfig {hunger: HungerRule::Unlimited, max_frames: 3, ..config(&[])});
+        assert_eq!(play(&mut game, &[Right, Right]), EndFrameState::Continue);
+        assert_eq!(play(&mut game, &[Right]), EndFrameState::GameOver{score: 1, cause: GameOverCause::TimedOut});
+    }
+
+    #[test]
+    fn running_into_the_body_ends_the_game() {
+        let mut game = SnakeGame::new(0, &config(&[(6, 4), (6, 5)]));
+        play(&mut game, &[Right, Down, Left]);
+        assert_eq!(game.length(), 3);
+        assert_eq!(body(&game), [(5, 5), (6, 5), (6, 4), (5, 4)]);
+
+        assert_eq!(play(&mut game, &[Up]), EndFrameState::GameOver{score: 3, cause: GameOverCause::HitSelf});
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn eating_a_fruit_is_reported_in_order() {
+        let mut game = SnakeGame::new(0, &config(&[(6, 4)]));
+        play(&mut game, &[Right]);
+        assert_eq!(game.events(), [
+            GameEvent::Moved{to: (6, 4), direction: Right},
+            GameEvent::AteFruit{at: (6, 4), kind: FruitKind::Normal},
+            GameEvent::Grew{length: 2},
+            GameEvent::FruitSpawned{at: (0, 9), kind: FruitKind::Normal},
+        ]);
+
+        play(&mut game, &[Right]);
+        assert_eq!(game.events(), [GameEvent::Moved{to: (7, 4), direction: Right}]);
+    }
+
+    #[test]
+    fn dying_is_reported_with_its_cause() {
+        let mut game = SnakeGame::new(0, &config(&[]));
+        assert_eq!(play(&mut game, &[Up, Up, Up, Up]), EndFrameState::Continue);
+        play(&mut game, &[Up]);
+        assert_eq!(game.events(), [GameEvent::GameOver{score: 1, cause: GameOverCause::HitWall}]);
+
+        //nothing happens once the game is over
+        game.to_next_frame();
+        assert!(game.events().is_empty());
+    }
+
+    //only the grid changes, the ray sensors don't look at the fruit list
+    fn put_fruit(snake_game: &mut SnakeGame, (x, y): (i32, i32), kind: FruitKind) {
+        let GameState::Game(game) = &mut snake_game.state else {panic!("the game is over")};
+        let point = GridPoint::from_point(x, y, game.board.grid.size).expect("the fruit should be on the board");
+        game.board.grid.set(&point, CellState::Fruit(kind));
+    }
+
+    #[test]
+    fn rays_see_the_nearest_of_each_thing_up_to_a_wall() {
+        let mut game = SnakeGame::new(0, &GameConfig {walls: vec![(6, 1)], ..config(&[])});
+        //the head is at (6, 4) and the tail it left at (5, 4)
+        play(&mut game, &[Right]);
+        put_fruit(&mut game, (6, 3), FruitKind::Bonus);
+        put_fruit(&mut game, (6, 2), FruitKind::Normal);
+        put_fruit(&mut game, (6, 0), FruitKind::Normal);
+        put_fruit(&mut game, (7, 4), FruitKind::Poison);
+        put_fruit(&mut game, (8, 4), FruitKind::Normal);
+        put_fruit(&mut game, (6, 6), FruitKind::Timed);
+
+        let rays = game.rays();
+        //the fruit behind the wall is out of sight
+        assert_eq!(rays[0], RaySensor {wall: 1.0 / 3.0, fruit: 1.0, bonus: 1.0, ..RaySensor::default()});
+        //poison is not fruit, the edge of the board is a wall
+        assert_eq!(rays[2], RaySensor {wall: 0.25, fruit: 0.5, poison: 1.0, ..RaySensor::default()});
+        assert_eq!(rays[4], RaySensor {wall: 1.0 / 6.0, fruit: 0.5, timed: 0.5, ..RaySensor::default()});
+        assert_eq!(rays[6], RaySensor {wall: 1.0 / 7.0, body: 1.0, ..RaySensor::default()});
+        assert_eq!(rays[1], RaySensor {wall: 0.25, ..RaySensor::default()});
+    }
+
+    #[test]
+    fn rays_on_a_wrapping_board_stop_at_the_head() {
+        let mut game = SnakeGame::new(0, &GameConfig {wrap: true, ..config(&[])});
+        play(&mut game, &[Right]);
+        put_fruit(&mut game, (2, 4), FruitKind::Normal);
+
+        let rays = game.rays();
+        //around the board to the tail, then back at the head
+        assert_eq!(rays[2], RaySensor {body: 1.0 / 9.0, fruit: 1.0 / 6.0, ..RaySensor::default()});
+        assert_eq!(rays[6], RaySensor {body: 1.0, fruit: 0.25, ..RaySensor::default()});
+        assert_eq!(rays[1], RaySensor::default());
+    }
+
+    #[test]
+    fn far_from_head_fruit_spawns_on_the_furthest_empty_cells() {
+        for seed in 0..10 {
+            let mut game = SnakeGame::new(seed, &GameConfig {fruit_count: 2, fruit_spawn: FruitSpawn::FarFromHead, ..GameConfig::default()});
+            //spawned around the head at (6, 4), (0, 9) is the only cell 11 away
+            play(&mut game, &[Right]);
+            let fruits = fruits(&game);
+            assert_eq!(fruits[0], (0, 9));
+            assert!([(0, 0), (0, 8), (1, 9)].contains(&fruits[1]), "seed {}: {:?}", seed, fruits);
+        }
+    }
+
+    #[test]
+    fn sequence_fruit_spawns_in_order_and_skips_taken_points() {
+        let sequence = vec![(1, 1), (6, 4), (2, 2), (3, 3)];
+        let mut game = SnakeGame::new(0, &GameConfig {fruit_count: 2, fruit_spawn: FruitSpawn::Sequence(sequence), ..config(&[])});
+        //the head is on (6, 4) by the time fruit spawns
+        play(&mut game, &[Right]);
+        assert_eq!(fruits(&game), [(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn sequence_fruit_falls_back_to_the_first_empty_cells() {
+        let sequence = vec![(6, 4), (0, 0)];
+        let mut game = SnakeGame::new(0, &GameConfig {fruit_count: 3, fruit_spawn: FruitSpawn::Sequence(sequence), ..config(&[(0, 0)])});
+        play(&mut game, &[Right]);
+        assert_eq!(fruits(&game), [(0, 0), (1, 0), (2, 0)]);
+    }
+
+    //every new fruit position, the number of frames and how it ended
+    fn play_greedy(seed: u64) -> (Vec<(i32, i32)>, u32, EndFrameState) {
+        let mut game = SnakeGame::new(seed, &GameConfig::default());
+        let mut seen: Vec<(i32, i32)> = Vec::new();
+        let mut frames = 0;
+        loop {
+            game.accept_input(crate::get_greedy_input(&game));
+            frames += 1;
+            let end = game.to_next_frame();
+            if end.is_over() {
+                return (seen, frames, end);
+            }
+            assert_occupied_cells_are_the_body(&game);
+            let fruit = fruits(&game)[0];
+            if seen.last() != Some(&fruit) {
+                seen.push(fruit);
+            }
+        }
+    }
 
+    //recorded with the engine from before the board and the snake were split up,
+    //the rng has to be drawn from in exactly the same way
+    #[test]
+    fn fruit_spawns_match_the_original_game() {
+        let (fruits, frames, end) = play_greedy(0);
+        assert_eq!(fruits, [(2, 4), (8, 9), (5, 6), (8, 1), (2, 8), (9, 1), (5, 6), (7, 8), (6, 4)]);
+        assert_eq!((frames, end.final_score()), (73, Some(9)));
+
+        let (fruits, frames, end) = play_greedy(42);
+        assert_eq!(fruits, [
+            (5, 6), (0, 0), (7, 6), (7, 9), (1, 9), (1, 2), (6, 0), (3, 2),
+            (6, 9), (1, 9), (2, 0), (0, 0), (0, 4), (9, 3), (5, 6), (9, 1),
+        ]);
+        assert_eq!((frames, end.final_score()), (151, Some(16)));
+    }
+
+    fn special_fruit_config() -> GameConfig {
+        GameConfig {
+            fruit_count: 3,
+            special_fruits: SpecialFruits {
+                bonus_chance: 0.2,
+                poison_chance: 0.3,
+                timed_chance: 0.2,
+                ..SpecialFruits::default()
+            },
+            ..GameConfig::default()
+        }
+    }
+
+    #[test]
+    fn a_loaded_game_plays_on_the_same_way() {
+        let mut game = SnakeGame::new(13, &special_fruit_config());
+        for _ in 0..50 {
+            game.accept_input(crate::get_greedy_input(&game));
+            assert!(!game.to_next_frame().is_over());
+        }
+
+        let path = std::env::temp_dir().join(format!("ai_snake_save_test_{}.json", std::process::id()));
+        let path = path.to_str().expect("the temp dir should be valid unicode");
+        game.save(path).expect("the game should save");
+        let mut loaded = SnakeGame::load(path).expect("the game should load");
+        let _ = std::fs::remove_file(path);
+
+        //the rng carries on from the same point, so fruit spawns in the same cells
+        while !game.is_over() {
+            assert_eq!(fruits(&loaded), fruits(&game));
+            assert_eq!(body(&loaded), body(&game));
+            let direction = crate::get_greedy_input(&game);
+            game.accept_input(direction);
+            loaded.accept_input(direction);
+            assert_eq!(loaded.to_next_frame(), game.to_next_frame());
+            assert_eq!(loaded.events(), game.events());
+        }
+        assert!(loaded.is_over());
+    }
+
+    //the board with its rng and the snake, without the history
+    fn snapshot(game: &SnakeGame) -> String {
+        serde_json::to_string(&game.state).expect("the game should serialize")
+    }
+
+    #[test]
+    fn rewinding_restores_the_board_snake_and_rng() {
+        let mut game = SnakeGame::new(13, &special_fruit_config()).with_history(10);
+        //the history keeps the game as it was going into every frame, after its input
+        let mut snapshots = Vec::new();
+        let mut last_end = EndFrameState::Continue;
+        while !game.is_over() {
+            game.accept_input(crate::get_greedy_input(&game));
+            snapshots.push(snapshot(&game));
+            last_end = game.to_next_frame();
+        }
+
+        assert_eq!(game.rewind(3), 3);
+        assert!(!game.is_over());
+        assert_eq!(snapshot(&game), snapshots[snapshots.len() - 3]);
+        //only the last 10 frames are kept
+        assert_eq!(game.rewind(100), 7);
+        assert_eq!(snapshot(&game), snapshots[snapshots.len() - 10]);
+        assert_eq!(game.rewind(1), 0);
+
+        //and it plays on the same way
+        for expected in &snapshots[snapshots.len() - 10..] {
+            game.accept_input(crate::get_greedy_input(&game));
+            assert_eq!(&snapshot(&game), expected);
+            game.to_next_frame();
+        }
+        assert!(game.is_over());
+        assert_eq!(game.to_next_frame(), last_end);
+    }
+}